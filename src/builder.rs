@@ -0,0 +1,389 @@
+//! Construction of an `Engine` with all its configuration.
+
+use crate::state_machine::{scope_fns, ScopeFns};
+use crate::{
+    target_delta, ClockSource, Engine, ErrorPolicy, FrameHooks, FramePacer, FrameStage, HookResult,
+    RealTimeClock, ScopedData, State, Time, DEFAULT_MAX_FIXED_STEPS,
+};
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// The maximum framerate used by `EngineBuilder` when none is specified.
+pub const DEFAULT_MAX_FPS: f32 = 60.0;
+
+/// An error returned by `EngineBuilder::build` when the configuration is invalid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    /// No initial state was given, so the engine would stop immediately.
+    NoInitialState,
+    /// The maximum framerate is NaN, not greater than 0, or so small that the
+    /// duration of a frame can't be represented by a `Duration`.
+    InvalidMaxFps(f32),
+    /// The fixed time step is 0.
+    InvalidFixedTime,
+    /// A fixed time step was given, but fixed updates can't run since the
+    /// maximum number of fixed steps per frame is 0.
+    NoFixedSteps,
+    /// The time scale is NaN, infinite or less than 0.
+    InvalidTimeScale(f32),
+    /// The profiler capacity is 0.
+    InvalidProfilerCapacity,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NoInitialState => write!(f, "no initial state was given"),
+            BuildError::InvalidMaxFps(max_fps) => {
+                write!(
+                    f,
+                    "max fps must be greater than 0 and not too small, got {}",
+                    max_fps
+                )
+            }
+            BuildError::InvalidFixedTime => write!(f, "the fixed time step must not be 0"),
+            BuildError::NoFixedSteps => write!(
+                f,
+                "a fixed time step was given but the max fixed steps per frame is 0"
+            ),
+            BuildError::InvalidTimeScale(time_scale) => write!(
+                f,
+                "time scale must be finite and not less than 0, got {}",
+                time_scale
+            ),
+            BuildError::InvalidProfilerCapacity => {
+                write!(f, "the profiler capacity must not be 0")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+fn no_post_update<SD>(_state_data: &mut SD, _time: &Time) {}
+
+/// Builds an `Engine`.
+/// ```rust,ignore
+/// let engine = EngineBuilder::new(0)
+///     .with_state(MyState)
+///     .post_update(|data, time| {})
+///     .max_fps(144.0)
+///     .build()?;
+/// ```
+/// # Generics:
+/// - SD: Type of the data passed to states.
+/// - F: Post update function.
+/// - C: Source of time used to advance `Time`.
+/// - R: Value returned by the post update function. Either `()` or a `Result`.
+pub struct EngineBuilder<SD, F, C = RealTimeClock, R = ()> {
+    states: Vec<Box<dyn State<SD>>>,
+    state_data: SD,
+    hooks: FrameHooks<SD>,
+    post_update: F,
+    clock: C,
+    max_fps: Option<f32>,
+    pacer: Option<Box<dyn FramePacer>>,
+    fixed_time: Option<Duration>,
+    max_fixed_steps: u32,
+    time_scale: f32,
+    profiler_capacity: Option<usize>,
+    error_policy: ErrorPolicy,
+    scopes: Option<ScopeFns<SD>>,
+    _hook_result: PhantomData<fn() -> R>,
+}
+
+impl<SD> EngineBuilder<SD, fn(&mut SD, &Time)> {
+    /// Creates a new `EngineBuilder` with the data passed to states.
+    /// By default, there is no post update function, the framerate is limited to
+    /// `DEFAULT_MAX_FPS` and the engine uses the real time.
+    pub fn new(state_data: SD) -> Self {
+        Self {
+            states: Vec::new(),
+            state_data,
+            hooks: FrameHooks::default(),
+            post_update: no_post_update,
+            clock: RealTimeClock::default(),
+            max_fps: Some(DEFAULT_MAX_FPS),
+            pacer: None,
+            fixed_time: None,
+            max_fixed_steps: DEFAULT_MAX_FIXED_STEPS,
+            time_scale: 1.0,
+            profiler_capacity: None,
+            error_policy: ErrorPolicy::default(),
+            scopes: None,
+            _hook_result: PhantomData,
+        }
+    }
+}
+
+impl<SD, F, C, R> EngineBuilder<SD, F, C, R> {
+    /// Adds a state to the initial stack. States are pushed in the order they are
+    /// added, so the last one added is at the top of the stack.
+    pub fn with_state<I: State<SD> + 'static>(mut self, state: I) -> Self {
+        self.states.push(Box::new(state));
+        self
+    }
+
+    /// Registers a hook that runs at the given stage of every frame.
+    /// See `Engine::add_hook`.
+    pub fn hook<H, HR>(mut self, stage: FrameStage, hook: H) -> Self
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        self.hooks.add(stage, 0, hook);
+        self
+    }
+
+    /// Registers a hook with an explicit order. See `Engine::add_hook_ordered`.
+    pub fn hook_ordered<H, HR>(mut self, stage: FrameStage, order: i32, hook: H) -> Self
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        self.hooks.add(stage, order, hook);
+        self
+    }
+
+    /// Registers a hook that runs at the start of every frame.
+    /// Same as `hook(FrameStage::PreUpdate, pre_update)`.
+    pub fn pre_update<H, HR>(self, pre_update: H) -> Self
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        self.hook(FrameStage::PreUpdate, pre_update)
+    }
+
+    /// Sets the function called at the end of every frame.
+    /// It can return either `()` or a `Result`.
+    pub fn post_update<F2, R2>(self, post_update: F2) -> EngineBuilder<SD, F2, C, R2>
+    where
+        F2: FnMut(&mut SD, &Time) -> R2,
+        R2: HookResult,
+    {
+        EngineBuilder {
+            states: self.states,
+            state_data: self.state_data,
+            hooks: self.hooks,
+            post_update,
+            clock: self.clock,
+            max_fps: self.max_fps,
+            pacer: self.pacer,
+            fixed_time: self.fixed_time,
+            max_fixed_steps: self.max_fixed_steps,
+            time_scale: self.time_scale,
+            profiler_capacity: self.profiler_capacity,
+            error_policy: self.error_policy,
+            scopes: self.scopes,
+            _hook_result: PhantomData,
+        }
+    }
+
+    /// Sets the source of time used by the engine. Defaults to `RealTimeClock`.
+    pub fn clock<C2: ClockSource>(self, clock: C2) -> EngineBuilder<SD, F, C2, R> {
+        EngineBuilder {
+            states: self.states,
+            state_data: self.state_data,
+            hooks: self.hooks,
+            post_update: self.post_update,
+            clock,
+            max_fps: self.max_fps,
+            pacer: self.pacer,
+            fixed_time: self.fixed_time,
+            max_fixed_steps: self.max_fixed_steps,
+            time_scale: self.time_scale,
+            profiler_capacity: self.profiler_capacity,
+            error_policy: self.error_policy,
+            scopes: self.scopes,
+            _hook_result: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of frames that can happen within a second.
+    /// An infinite value uncaps the framerate.
+    pub fn max_fps(mut self, max_fps: f32) -> Self {
+        self.max_fps = Some(max_fps);
+        self
+    }
+
+    /// Removes the framerate limit. The engine will run as fast as it can.
+    pub fn uncapped(mut self) -> Self {
+        self.max_fps = None;
+        self
+    }
+
+    /// Sets the strategy used to wait at the end of frames. Defaults to `SpinSleepPacer`.
+    pub fn pacer<P: FramePacer + 'static>(mut self, pacer: P) -> Self {
+        self.pacer = Some(Box::new(pacer));
+        self
+    }
+
+    /// Sets the interval at which `State::fixed_update` is called.
+    pub fn fixed_time(mut self, fixed_time: Duration) -> Self {
+        self.fixed_time = Some(fixed_time);
+        self
+    }
+
+    /// Sets the maximum number of fixed updates that can run during a single frame.
+    /// Defaults to `DEFAULT_MAX_FIXED_STEPS`.
+    pub fn max_fixed_steps(mut self, max_fixed_steps: u32) -> Self {
+        self.max_fixed_steps = max_fixed_steps;
+        self
+    }
+
+    /// Sets the time multiplier. See `Time::set_time_scale`.
+    pub fn time_scale(mut self, time_scale: f32) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+
+    /// Enables the frame profiler, keeping the last `capacity` frames.
+    pub fn profiler(mut self, capacity: usize) -> Self {
+        self.profiler_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets what happens to the state stack when a state or hook returns an error.
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Enables the scopes of the state data, so that the data inserted in a scope
+    /// by a state is dropped when the state stops. See `StateMachine::enable_scopes`.
+    pub fn scoped_data(mut self) -> Self
+    where
+        SD: ScopedData,
+    {
+        self.scopes = Some(scope_fns());
+        self
+    }
+}
+
+impl<SD, F: FnMut(&mut SD, &Time) -> R, C: ClockSource, R: HookResult> EngineBuilder<SD, F, C, R> {
+    /// Validates the configuration and builds the `Engine`, starting the initial states.
+    pub fn build(self) -> Result<Engine<SD, F, C, R>, BuildError> {
+        if self.states.is_empty() {
+            return Err(BuildError::NoInitialState);
+        }
+        if let Some(max_fps) = self.max_fps {
+            target_delta(max_fps)?;
+        }
+        if let Some(fixed_time) = self.fixed_time {
+            if fixed_time == Duration::from_secs(0) {
+                return Err(BuildError::InvalidFixedTime);
+            }
+            if self.max_fixed_steps == 0 {
+                return Err(BuildError::NoFixedSteps);
+            }
+        }
+        if !self.time_scale.is_finite() || self.time_scale < 0.0 {
+            return Err(BuildError::InvalidTimeScale(self.time_scale));
+        }
+        if self.profiler_capacity == Some(0) {
+            return Err(BuildError::InvalidProfilerCapacity);
+        }
+
+        let mut engine = Engine::without_states(self.state_data, self.post_update, self.clock);
+        engine.hooks = self.hooks;
+        match self.max_fps {
+            Some(max_fps) => engine.set_max_fps(max_fps),
+            None => engine.set_uncapped(),
+        }
+        if let Some(pacer) = self.pacer {
+            engine.pacer = pacer;
+        }
+        if let Some(fixed_time) = self.fixed_time {
+            engine.time.set_fixed_time(fixed_time);
+        }
+        engine.set_max_fixed_steps(self.max_fixed_steps);
+        engine.time.set_time_scale(self.time_scale);
+        if let Some(capacity) = self.profiler_capacity {
+            engine.enable_profiler(capacity);
+        }
+        engine.set_error_policy(self.error_policy);
+        engine.state_machine.set_scopes(self.scopes);
+        for state in self.states {
+            engine.state_machine.push(state, &mut engine.state_data);
+        }
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    struct MyState;
+    impl State<u32> for MyState {
+        fn fixed_update(&mut self, state_data: &mut u32) -> StateTransition<u32> {
+            *state_data += 1;
+            StateTransition::None
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let mut engine = EngineBuilder::new(0)
+            .with_state(MyState)
+            .with_state(MyState)
+            .pre_update(|s, _| *s += 10)
+            .post_update(|s, _| *s += 100)
+            .clock(ManualClock::default())
+            .fixed_time(Duration::from_millis(10))
+            .time_scale(2.0)
+            .profiler(8)
+            .build()
+            .unwrap();
+        engine.clock.advance(Duration::from_millis(10));
+        assert!(engine.engine_frame(true));
+        assert_eq!(engine.state_data, 111);
+        assert_eq!(engine.time.delta_time(), Duration::from_millis(20));
+        assert!((engine.max_fps().unwrap() - DEFAULT_MAX_FPS).abs() < 0.01);
+        assert_eq!(engine.profiler().unwrap().capacity(), 8);
+    }
+
+    #[test]
+    fn builder_errors() {
+        assert_eq!(
+            EngineBuilder::new(0).build().err(),
+            Some(BuildError::NoInitialState)
+        );
+        assert_eq!(
+            EngineBuilder::new(0)
+                .with_state(MyState)
+                .max_fps(0.0)
+                .build()
+                .err(),
+            Some(BuildError::InvalidMaxFps(0.0))
+        );
+        assert_eq!(
+            EngineBuilder::new(0)
+                .with_state(MyState)
+                .max_fps(1e-30)
+                .build()
+                .err(),
+            Some(BuildError::InvalidMaxFps(1e-30))
+        );
+        assert_eq!(
+            EngineBuilder::new(0)
+                .with_state(MyState)
+                .fixed_time(Duration::from_millis(10))
+                .max_fixed_steps(0)
+                .build()
+                .err(),
+            Some(BuildError::NoFixedSteps)
+        );
+        assert_eq!(
+            EngineBuilder::new(0)
+                .with_state(MyState)
+                .time_scale(-1.0)
+                .build()
+                .err(),
+            Some(BuildError::InvalidTimeScale(-1.0))
+        );
+    }
+}
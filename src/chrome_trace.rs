@@ -0,0 +1,139 @@
+//! Export of engine frames in the Chrome trace event format.
+//! The files can be opened in chrome://tracing or in Perfetto.
+
+use crate::{FrameProfile, TransitionKind};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes trace events using the JSON array format of the Chrome trace event format.
+/// The closing bracket is written when the tracer is dropped. It is optional in this
+/// format, so files from a crashed process can still be opened.
+pub struct ChromeTracer {
+    writer: Box<dyn Write>,
+    first_event: bool,
+}
+
+impl ChromeTracer {
+    /// Creates a new `ChromeTracer` writing to the file at the given path.
+    /// The file is overwritten if it already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Creates a new `ChromeTracer` writing to the given writer.
+    pub fn from_writer<W: Write + 'static>(writer: W) -> io::Result<Self> {
+        let mut writer: Box<dyn Write> = Box::new(writer);
+        writer.write_all(b"[")?;
+        Ok(Self {
+            writer,
+            first_event: true,
+        })
+    }
+
+    /// Writes the spans of a frame and of each of its phases.
+    /// `start` is the time at which the frame started, as given by the engine clock.
+    pub fn write_frame(&mut self, start: Duration, profile: &FrameProfile) -> io::Result<()> {
+        self.write_event(&format!(
+            r#"{{"name":"frame","ph":"X","pid":1,"tid":1,"ts":{},"dur":{},"args":{{"frame_number":{}}}}}"#,
+            start.as_micros(),
+            profile.total().as_micros(),
+            profile.frame_number,
+        ))?;
+        let phases = [
+            ("pre_update", profile.pre_update),
+            ("fixed_update", profile.fixed_update),
+            ("update", profile.update),
+            ("sleep", profile.sleep),
+            ("post_update", profile.post_update),
+            ("render", profile.render),
+            ("end_frame", profile.end_frame),
+        ];
+        let mut phase_start = start;
+        for (name, duration) in phases.iter() {
+            self.write_event(&format!(
+                r#"{{"name":"{}","ph":"X","pid":1,"tid":1,"ts":{},"dur":{}}}"#,
+                name,
+                phase_start.as_micros(),
+                duration.as_micros(),
+            ))?;
+            phase_start += *duration;
+        }
+        Ok(())
+    }
+
+    /// Writes an instant event for a state transition that happened at the given time.
+    pub fn write_transition(&mut self, time: Duration, kind: TransitionKind) -> io::Result<()> {
+        self.write_event(&format!(
+            r#"{{"name":"{:?}","cat":"state_transition","ph":"i","s":"t","pid":1,"tid":1,"ts":{}}}"#,
+            kind,
+            time.as_micros(),
+        ))
+    }
+
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        if !self.first_event {
+            self.writer.write_all(b",")?;
+        }
+        self.first_event = false;
+        self.writer.write_all(b"\n")?;
+        self.writer.write_all(event.as_bytes())
+    }
+}
+
+impl Drop for ChromeTracer {
+    fn drop(&mut self) {
+        let _ = self.writer.write_all(b"\n]\n");
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let buffer = SharedBuffer::default();
+        let mut tracer = ChromeTracer::from_writer(buffer.clone()).unwrap();
+        let profile = FrameProfile {
+            frame_number: 1,
+            update: Duration::from_micros(10),
+            sleep: Duration::from_micros(20),
+            end_frame: Duration::from_micros(5),
+            ..FrameProfile::default()
+        };
+        tracer
+            .write_frame(Duration::from_micros(100), &profile)
+            .unwrap();
+        tracer
+            .write_transition(Duration::from_micros(110), TransitionKind::Pop)
+            .unwrap();
+        drop(tracer);
+
+        let json = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert!(json.starts_with("[\n{\"name\":\"frame\""));
+        assert!(json.ends_with("}\n]\n"));
+        assert!(json.contains(r#""ts":100,"dur":35,"args":{"frame_number":1}"#));
+        assert!(json.contains(r#""name":"sleep","ph":"X","pid":1,"tid":1,"ts":110,"dur":20"#));
+        assert!(json.contains(r#""name":"Pop","cat":"state_transition","ph":"i""#));
+    }
+}
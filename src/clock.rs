@@ -0,0 +1,84 @@
+//! Sources of time used by the engine to advance `Time`.
+
+use std::time::{Duration, Instant};
+
+/// A source of time for the engine.
+/// The engine reads it at the start of every frame to know how much time elapsed
+/// since the previous frame.
+pub trait ClockSource {
+    /// Returns the time elapsed since an arbitrary point in the past.
+    /// It must never go backwards.
+    fn now(&self) -> Duration;
+    /// Returns whether this clock follows the wall clock.
+    /// The engine doesn't sleep when using a clock that doesn't, since sleeping
+    /// would not make it advance.
+    fn is_real_time(&self) -> bool {
+        true
+    }
+}
+
+/// A monotonic clock following the real time.
+/// This is the clock used by default.
+#[derive(Clone, Copy, Debug)]
+pub struct RealTimeClock {
+    start: Instant,
+}
+
+impl Default for RealTimeClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl ClockSource for RealTimeClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only advances when told to.
+/// Use it for deterministic runs, such as tests, replays or simulations running
+/// faster than real time.
+/// ```rust,ignore
+/// engine.clock.advance(Duration::from_millis(16));
+/// engine.engine_frame(true);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ManualClock {
+    now: Duration,
+}
+
+impl ManualClock {
+    /// Moves the clock forward by the given duration.
+    pub fn advance(&mut self, delta: Duration) {
+        self.now += delta;
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+
+    fn is_real_time(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn manual_clock() {
+        let mut clock = ManualClock::default();
+        assert_eq!(clock.now(), Duration::from_secs(0));
+        clock.advance(Duration::from_millis(10));
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::from_millis(15));
+        assert!(!clock.is_real_time());
+    }
+}
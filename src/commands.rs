@@ -0,0 +1,96 @@
+//! Changes to the state stack requested from outside of the states, such as from hooks,
+//! a debug console or other threads.
+
+use crate::State;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A change to the state stack, applied by the state machine when it applies its commands.
+pub enum StateCommand<S> {
+    /// Push a new state on the stack.
+    Push(Box<dyn State<S> + Send>),
+    /// Pop the state at the top of the stack.
+    Pop,
+    /// Replace the state at the top of the stack.
+    Switch(Box<dyn State<S> + Send>),
+    /// Pop all states and exit the state machine.
+    Quit,
+    /// Pop all states except the one at the bottom of the stack.
+    /// The states in between are stopped without being resumed, and the bottom state
+    /// is resumed once.
+    Clear,
+}
+
+/// A handle used to queue commands in a `StateMachine`.
+/// It can be cloned and sent to other threads, so the states it pushes must be `Send`.
+pub struct CommandSender<S> {
+    sender: Sender<StateCommand<S>>,
+}
+
+impl<S> Clone for CommandSender<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<S> CommandSender<S> {
+    /// Queues a command.
+    /// Commands sent after the state machine is dropped are discarded.
+    pub fn send(&self, command: StateCommand<S>) {
+        let _ = self.sender.send(command);
+    }
+
+    /// Queues pushing a new state on the stack.
+    pub fn push<T: State<S> + Send + 'static>(&self, state: T) {
+        self.send(StateCommand::Push(Box::new(state)));
+    }
+
+    /// Queues popping the state at the top of the stack.
+    pub fn pop(&self) {
+        self.send(StateCommand::Pop);
+    }
+
+    /// Queues replacing the state at the top of the stack.
+    pub fn switch<T: State<S> + Send + 'static>(&self, state: T) {
+        self.send(StateCommand::Switch(Box::new(state)));
+    }
+
+    /// Queues stopping all the states.
+    pub fn quit(&self) {
+        self.send(StateCommand::Quit);
+    }
+
+    /// Queues popping all the states except the one at the bottom of the stack.
+    pub fn clear(&self) {
+        self.send(StateCommand::Clear);
+    }
+}
+
+/// The commands queued in a state machine that weren't applied yet.
+pub(crate) struct CommandQueue<S> {
+    sender: CommandSender<S>,
+    receiver: Receiver<StateCommand<S>>,
+}
+
+impl<S> Default for CommandQueue<S> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: CommandSender { sender },
+            receiver,
+        }
+    }
+}
+
+impl<S> CommandQueue<S> {
+    pub(crate) fn sender(&self) -> &CommandSender<S> {
+        &self.sender
+    }
+
+    /// Returns the commands queued so far. Commands queued while they are applied wait
+    /// for the next call.
+    pub(crate) fn drain(&self) -> Vec<StateCommand<S>> {
+        self.receiver.try_iter().collect()
+    }
+}
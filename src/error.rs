@@ -0,0 +1,36 @@
+//! Handling of the errors returned by fallible states and hooks.
+
+use crate::StateError;
+
+/// What the engine does with the state stack when a state, a hook or the post update
+/// function returns an error. The error is returned to the caller in all cases.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Pop the state that returned the error, along with the states above it.
+    /// Errors returned by hooks or the post update function leave the stack untouched.
+    PopState,
+    /// Stop all the states, ending the engine loop.
+    Quit,
+    /// Leave the state stack untouched. The engine can be run again.
+    #[default]
+    Continue,
+}
+
+/// The value returned by the post update function.
+/// It is implemented for `()` and for `Result`, so the function can be fallible.
+pub trait HookResult {
+    /// Converts the value into a `Result`.
+    fn into_result(self) -> Result<(), StateError>;
+}
+
+impl HookResult for () {
+    fn into_result(self) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+impl<E: Into<StateError>> HookResult for Result<(), E> {
+    fn into_result(self) -> Result<(), StateError> {
+        self.map_err(Into::into)
+    }
+}
@@ -0,0 +1,51 @@
+//! Events sent to the engine from outside of the states, such as input or network events.
+
+use std::any::Any;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// An event waiting to be delivered to the states.
+pub type Event = Box<dyn Any + Send>;
+
+/// A handle used to send events to the engine. They are delivered to the states at the
+/// start of the next frame, in the order they were sent.
+/// It can be cloned and sent to other threads.
+#[derive(Clone, Debug)]
+pub struct EventSender {
+    sender: Sender<Event>,
+}
+
+impl EventSender {
+    /// Sends an event to the engine.
+    /// Events sent after the engine is dropped are discarded.
+    pub fn send<E: Any + Send>(&self, event: E) {
+        let _ = self.sender.send(Box::new(event));
+    }
+}
+
+/// The events sent to an engine that weren't delivered yet.
+pub(crate) struct EventQueue {
+    sender: EventSender,
+    receiver: Receiver<Event>,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: EventSender { sender },
+            receiver,
+        }
+    }
+}
+
+impl EventQueue {
+    pub(crate) fn sender(&self) -> &EventSender {
+        &self.sender
+    }
+
+    /// Returns the events sent so far. Events sent while they are handled wait for the
+    /// next call.
+    pub(crate) fn drain(&self) -> Vec<Event> {
+        self.receiver.try_iter().collect()
+    }
+}
@@ -0,0 +1,132 @@
+//! Functions registered to run at a given stage of every engine frame.
+
+use crate::{HookResult, StateError, Time};
+
+pub(crate) type BoxedHook<SD> = Box<dyn FnMut(&mut SD, &Time) -> Result<(), StateError>>;
+
+/// The stages of an engine frame, in the order they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrameStage {
+    /// At the start of the frame, before any state runs.
+    PreUpdate,
+    /// After each fixed update of the states.
+    FixedUpdate,
+    /// After the update of the states.
+    Update,
+    /// After sleeping and after the post update function.
+    PostUpdate,
+    /// After the render of the states.
+    Render,
+    /// At the end of the frame.
+    EndFrame,
+}
+
+impl FrameStage {
+    /// All the stages, in the order they run.
+    pub const ALL: [FrameStage; 6] = [
+        FrameStage::PreUpdate,
+        FrameStage::FixedUpdate,
+        FrameStage::Update,
+        FrameStage::PostUpdate,
+        FrameStage::Render,
+        FrameStage::EndFrame,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Identifies a hook registered in an engine. Used to remove it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+struct RegisteredHook<SD> {
+    id: HookId,
+    order: i32,
+    hook: BoxedHook<SD>,
+}
+
+/// The hooks of every frame stage, sorted by the order in which they run.
+pub(crate) struct FrameHooks<SD> {
+    next_id: u64,
+    stages: [Vec<RegisteredHook<SD>>; 6],
+}
+
+impl<SD> Default for FrameHooks<SD> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            stages: Default::default(),
+        }
+    }
+}
+
+impl<SD> FrameHooks<SD> {
+    /// Adds a hook after all the hooks of the stage with a lower or equal order.
+    pub(crate) fn add<H, HR>(&mut self, stage: FrameStage, order: i32, mut hook: H) -> HookId
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        let hooks = &mut self.stages[stage.index()];
+        let position = hooks.partition_point(|registered| registered.order <= order);
+        hooks.insert(
+            position,
+            RegisteredHook {
+                id,
+                order,
+                hook: Box::new(move |state_data, time| hook(state_data, time).into_result()),
+            },
+        );
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: HookId) -> bool {
+        for hooks in self.stages.iter_mut() {
+            if let Some(position) = hooks.iter().position(|registered| registered.id == id) {
+                hooks.remove(position);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Runs the hooks of the stage in order, stopping at the first error.
+    pub(crate) fn run(
+        &mut self,
+        stage: FrameStage,
+        state_data: &mut SD,
+        time: &Time,
+    ) -> Result<(), StateError> {
+        self.stages[stage.index()]
+            .iter_mut()
+            .try_for_each(|registered| (registered.hook)(state_data, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_order() {
+        let mut hooks = FrameHooks::<Vec<i32>>::default();
+        hooks.add(FrameStage::Update, 0, |s, _| s.push(1));
+        let removed = hooks.add(FrameStage::Update, 0, |s, _| s.push(2));
+        hooks.add(FrameStage::Update, -1, |s, _| s.push(3));
+        hooks.add(FrameStage::Update, 0, |s, _| s.push(4));
+        hooks.add(FrameStage::Render, 0, |s, _| s.push(5));
+
+        assert!(hooks.remove(removed));
+        assert!(!hooks.remove(removed));
+
+        let mut data = vec![];
+        hooks
+            .run(FrameStage::Update, &mut data, &Time::default())
+            .unwrap();
+        assert_eq!(data, vec![3, 1, 4]);
+    }
+}
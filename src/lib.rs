@@ -4,17 +4,78 @@
 //! When sleeping is disabled, this crate is compatible with WASM. When enabled,
 //! the game loop will run at the target framerate.
 #![deny(missing_docs)]
-pub use game_clock::*;
-pub use game_state_machine::*;
-use spin_sleep::LoopHelper;
+pub use builder::*;
+#[cfg(feature = "chrome_trace")]
+pub use chrome_trace::*;
+pub use clock::*;
+pub use commands::*;
+pub use error::*;
+use events::EventQueue;
+pub use events::*;
+use hooks::FrameHooks;
+pub use hooks::*;
+pub use nested::*;
+pub use pacing::*;
+pub use panic::*;
+use panic::{panic_message, PanicIsolation};
+pub use profiler::*;
+pub use resources::*;
+pub use shutdown::*;
+pub use state_machine::*;
+pub use stats::*;
+use std::any::Any;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+pub use time::*;
+
+mod builder;
+#[cfg(feature = "chrome_trace")]
+mod chrome_trace;
+mod clock;
+mod commands;
+mod error;
+mod events;
+mod hooks;
+mod nested;
+mod pacing;
+mod panic;
+mod profiler;
+mod resources;
+mod shutdown;
+mod state_machine;
+mod stats;
+mod time;
+
+/// The default maximum number of fixed updates that can run during a single frame.
+pub const DEFAULT_MAX_FIXED_STEPS: u32 = 5;
+
+/// A boxed post update function. Used by default by `Engine`, so that the engine
+/// type can be named as `Engine<SD>`.
+/// For a fallible post update function returning `R`, the engine type is
+/// `Engine<SD, BoxedPostUpdate<SD, R>, C, R>`.
+pub type BoxedPostUpdate<SD, R = ()> = Box<dyn FnMut(&mut SD, &Time) -> R>;
 
 /// The main structure of the engine core loop.
 /// It holds the data necessary to the execution of a game engine.
+///
+/// The post update function is stored by value, so it has no runtime cost. Since
+/// closures can't be named, `Engine::boxed` can be used to get an `Engine<SD>`,
+/// which can be stored in a struct field or returned from a function.
 /// # Generics:
 /// - SD: Type of the data passed to states.
-/// - F: Post update function.
-pub struct Engine<SD, F: Fn(&mut SD, &Time)> {
-    loop_helper: LoopHelper,
+/// - F: Post update function. Defaults to `BoxedPostUpdate`.
+/// - C: Source of time used to advance `Time`.
+/// - R: Value returned by the post update function. Either `()` or a `Result`.
+pub struct Engine<
+    SD,
+    F: FnMut(&mut SD, &Time) -> R = BoxedPostUpdate<SD>,
+    C: ClockSource = RealTimeClock,
+    R: HookResult = (),
+> {
+    pacer: Box<dyn FramePacer>,
+    target_delta: Option<Duration>,
+    last_frame_start: Duration,
     /// The inner state machine.
     /// You can verify that it is still running using
     /// ```rust,ignore
@@ -25,35 +86,291 @@ pub struct Engine<SD, F: Fn(&mut SD, &Time)> {
     pub state_data: SD,
     /// The inner clock keeping track of time.
     pub time: Time,
+    /// The source of time read at the start of every frame.
+    pub clock: C,
     post_update: F,
+    hooks: FrameHooks<SD>,
+    max_fixed_steps: u32,
+    frame_stats: FrameStats,
+    profiler: Option<FrameProfiler>,
+    #[cfg(feature = "chrome_trace")]
+    chrome_tracer: Option<ChromeTracer>,
+    error_policy: ErrorPolicy,
+    panic_isolation: Option<PanicIsolation<SD>>,
+    quit_handle: QuitHandle,
+    events: EventQueue,
+    _hook_result: PhantomData<fn() -> R>,
 }
 
-impl<SD, F: Fn(&mut SD, &Time)> Engine<SD, F> {
+impl<SD, F: FnMut(&mut SD, &Time) -> R, R: HookResult> Engine<SD, F, RealTimeClock, R> {
     /// Creates a new `Engine`.
+    /// To configure more of the engine, use `EngineBuilder` instead.
     /// The initial state and state data will be used to initialize the state machine.
     /// The post update function will be stored. It is called at the end of game frames.
     /// `max_fps` specifies the maximum number of frames that can happen within a second.
+    /// An infinite value uncaps the framerate.
     /// # Generics:
     /// I: Initial state.
     pub fn new<I: State<SD> + 'static>(
         init_state: I,
-        mut init_state_data: SD,
+        init_state_data: SD,
         post_update: F,
         max_fps: f32,
     ) -> Self {
-        let loop_helper = LoopHelper::builder().build_with_target_rate(max_fps);
-        let mut state_machine = StateMachine::default();
-        let time = Time::default();
-        state_machine.push(Box::new(init_state), &mut init_state_data);
+        Self::with_clock(
+            init_state,
+            init_state_data,
+            post_update,
+            max_fps,
+            RealTimeClock::default(),
+        )
+    }
+}
+
+impl<SD, F: FnMut(&mut SD, &Time) -> R, C: ClockSource, R: HookResult> Engine<SD, F, C, R> {
+    /// Creates a new `Engine` reading time from the provided clock.
+    /// See `Engine::new` for the other arguments.
+    /// # Generics:
+    /// I: Initial state.
+    pub fn with_clock<I: State<SD> + 'static>(
+        init_state: I,
+        init_state_data: SD,
+        post_update: F,
+        max_fps: f32,
+        clock: C,
+    ) -> Self {
+        let mut engine = Self::without_states(init_state_data, post_update, clock);
+        engine
+            .state_machine
+            .push(Box::new(init_state), &mut engine.state_data);
+        engine.set_max_fps(max_fps);
+        engine
+    }
+
+    /// Creates an uncapped engine with an empty state machine, which isn't running.
+    pub(crate) fn without_states(state_data: SD, post_update: F, clock: C) -> Self {
         Self {
-            loop_helper,
-            state_machine,
-            state_data: init_state_data,
-            time,
+            pacer: Box::new(SpinSleepPacer::default()),
+            target_delta: None,
+            last_frame_start: clock.now(),
+            clock,
+            state_machine: StateMachine::default(),
+            state_data,
+            time: Time::default(),
             post_update,
+            hooks: FrameHooks::default(),
+            max_fixed_steps: DEFAULT_MAX_FIXED_STEPS,
+            frame_stats: FrameStats::default(),
+            profiler: None,
+            #[cfg(feature = "chrome_trace")]
+            chrome_tracer: None,
+            error_policy: ErrorPolicy::default(),
+            panic_isolation: None,
+            quit_handle: QuitHandle::default(),
+            events: EventQueue::default(),
+            _hook_result: PhantomData,
+        }
+    }
+
+    fn map_post_update<F2: FnMut(&mut SD, &Time) -> R2, R2: HookResult>(
+        self,
+        map: impl FnOnce(F) -> F2,
+    ) -> Engine<SD, F2, C, R2> {
+        Engine {
+            pacer: self.pacer,
+            target_delta: self.target_delta,
+            last_frame_start: self.last_frame_start,
+            clock: self.clock,
+            state_machine: self.state_machine,
+            state_data: self.state_data,
+            time: self.time,
+            post_update: map(self.post_update),
+            hooks: self.hooks,
+            max_fixed_steps: self.max_fixed_steps,
+            frame_stats: self.frame_stats,
+            profiler: self.profiler,
+            #[cfg(feature = "chrome_trace")]
+            chrome_tracer: self.chrome_tracer,
+            error_policy: self.error_policy,
+            panic_isolation: self.panic_isolation,
+            quit_handle: self.quit_handle,
+            events: self.events,
+            _hook_result: PhantomData,
         }
     }
 
+    /// Sets the maximum number of fixed updates that can run during a single frame.
+    /// When a frame takes longer than this many fixed steps, the remaining time is
+    /// dropped instead of being caught up on later. This prevents a slow frame from
+    /// causing even slower frames.
+    /// The fixed step duration itself is configured using `Time::set_fixed_time`.
+    pub fn set_max_fixed_steps(&mut self, max_fixed_steps: u32) {
+        self.max_fixed_steps = max_fixed_steps;
+    }
+
+    /// Returns the maximum number of fixed updates that can run during a single frame.
+    pub fn max_fixed_steps(&self) -> u32 {
+        self.max_fixed_steps
+    }
+
+    /// Sets the maximum number of frames that can happen within a second.
+    /// It takes effect on the next frame. An infinite value uncaps the framerate.
+    /// ## Panics
+    /// This will panic if `max_fps` is NaN, not greater than 0, or so small that the
+    /// duration of a frame can't be represented by a `Duration`.
+    pub fn set_max_fps(&mut self, max_fps: f32) {
+        self.target_delta = target_delta(max_fps).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Removes the framerate limit. The engine will run as fast as it can.
+    pub fn set_uncapped(&mut self) {
+        self.target_delta = None;
+    }
+
+    /// Returns the maximum number of frames that can happen within a second,
+    /// or None if the framerate is uncapped.
+    pub fn max_fps(&self) -> Option<f32> {
+        self.target_delta.map(|delta| 1.0 / delta.as_secs_f32())
+    }
+
+    /// Sets the strategy used to wait at the end of frames when running faster than
+    /// the target framerate. It takes effect on the next frame.
+    /// Defaults to `SpinSleepPacer`.
+    pub fn set_pacer<P: FramePacer + 'static>(&mut self, pacer: P) {
+        self.pacer = Box::new(pacer);
+    }
+
+    /// Registers a hook that runs at the given stage of every frame.
+    /// It receives the same arguments as the post update function and can also be fallible.
+    /// Like the post update function, hooks can own and mutate the data they capture.
+    /// Hooks of a stage run in the order they were added.
+    /// Returns an id that can be used to remove the hook.
+    pub fn add_hook<H, HR>(&mut self, stage: FrameStage, hook: H) -> HookId
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        self.hooks.add(stage, 0, hook)
+    }
+
+    /// Registers a hook like `add_hook`, but with an explicit order.
+    /// Hooks with a lower order run first. Hooks added using `add_hook` have an order of 0
+    /// and hooks with the same order run in the order they were added.
+    pub fn add_hook_ordered<H, HR>(&mut self, stage: FrameStage, order: i32, hook: H) -> HookId
+    where
+        H: FnMut(&mut SD, &Time) -> HR + 'static,
+        HR: HookResult,
+    {
+        self.hooks.add(stage, order, hook)
+    }
+
+    /// Removes a hook. Returns false if there was no hook with this id.
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        self.hooks.remove(id)
+    }
+
+    /// Sets what happens to the state stack when a state or the post update function
+    /// returns an error. Defaults to `ErrorPolicy::Continue`.
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    /// Returns what happens to the state stack when a state or the post update function
+    /// returns an error.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    /// Runs each frame under `catch_unwind`, so that a panic in a state or in the
+    /// post update function doesn't end the engine loop.
+    /// When a frame panics, the handler receives a report of the panic and the policy
+    /// is applied to the state stack. The state data might be left in the state it was
+    /// in when the panic happened.
+    pub fn enable_panic_isolation<H: FnMut(&PanicReport) + 'static>(
+        &mut self,
+        policy: PanicPolicy<SD>,
+        handler: H,
+    ) {
+        self.panic_isolation = Some(PanicIsolation {
+            policy,
+            handler: Box::new(handler),
+        });
+    }
+
+    /// Lets panics end the engine loop again.
+    pub fn disable_panic_isolation(&mut self) {
+        self.panic_isolation = None;
+    }
+
+    /// Returns a handle that can ask the engine to quit at the start of its next frame.
+    /// For example, it can be sent to another thread or registered for termination
+    /// signals using `QuitHandle::quit_on_termination_signals`.
+    pub fn quit_handle(&self) -> QuitHandle {
+        self.quit_handle.clone()
+    }
+
+    /// Queues an event. It is delivered to the states at the start of the next frame,
+    /// after the pre update hooks. See `StateMachine::handle_event`.
+    pub fn send_event<E: Any + Send>(&self, event: E) {
+        self.events.sender().send(event);
+    }
+
+    /// Returns a handle that can queue events from outside of the engine, for example
+    /// from an input or network thread.
+    pub fn event_sender(&self) -> EventSender {
+        self.events.sender().clone()
+    }
+
+    /// Returns the statistics about the duration of the last frames.
+    /// Frames are only measured when `engine_frame` is called with sleep set to true.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// Returns the frame statistics mutably, for example to change their window.
+    pub fn frame_stats_mut(&mut self) -> &mut FrameStats {
+        &mut self.frame_stats
+    }
+
+    /// Starts recording the time spent in each phase of the frames.
+    /// The last `capacity` frames are kept. Durations are measured using the engine clock.
+    pub fn enable_profiler(&mut self, capacity: usize) {
+        self.profiler = Some(FrameProfiler::new(capacity));
+    }
+
+    /// Stops recording frame profiles and returns the ones recorded so far.
+    pub fn disable_profiler(&mut self) -> Option<FrameProfiler> {
+        self.profiler.take()
+    }
+
+    /// Returns the frame profiler, if enabled.
+    pub fn profiler(&self) -> Option<&FrameProfiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Starts writing frames, their phases and state transitions to a Chrome trace
+    /// file at the given path. The file can be opened in chrome://tracing or Perfetto.
+    /// State transitions are placed at the end of the update phase of their frame.
+    /// Transitions performed when quitting or recovering from an error or a panic are
+    /// placed at the time they happened.
+    /// Tracing stops if writing to the file fails.
+    #[cfg(feature = "chrome_trace")]
+    pub fn start_chrome_trace<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> std::io::Result<()> {
+        self.chrome_tracer = Some(ChromeTracer::create(path)?);
+        self.state_machine.record_transitions(true);
+        Ok(())
+    }
+
+    /// Stops the Chrome trace and closes its file.
+    #[cfg(feature = "chrome_trace")]
+    pub fn stop_chrome_trace(&mut self) {
+        self.chrome_tracer = None;
+        self.state_machine.record_transitions(false);
+    }
+
     /// Runs a single frame of the engine. Returns false if this was the last
     /// frame the engine will run and returns true if the engine can be run again.
     /// The sleep argument specifies if this function should take care of sleeping
@@ -66,35 +383,297 @@ impl<SD, F: Fn(&mut SD, &Time)> Engine<SD, F> {
     /// another loop. For instance, winit and bracket-lib are both libraries that
     /// require control of the main loop, for compatibility with mobile and web platforms.
     /// Here, we can let them take care of the main loop and simple call `engine_frame`.
+    ///
+    /// When the clock isn't following the real time (see `ClockSource::is_real_time`),
+    /// the engine never sleeps and time only advances as much as the clock did.
+    ///
+    /// Fixed updates run before the state update, once for every `Time::fixed_time`
+    /// that elapsed. Since they rely on the time being advanced, they only run when
+    /// sleep is true.
+    /// The state render runs last, receiving `Time::interpolation_alpha`.
+    /// Hooks run at each `FrameStage`, after the states and the post update function.
+    ///
+    /// ## Panics
+    /// This will panic if a state or the post update function returns an error.
+    /// Use `try_engine_frame` to handle errors instead.
     pub fn engine_frame(&mut self, sleep: bool) -> bool {
+        match self.try_engine_frame(sleep) {
+            Ok(running) => running,
+            Err(error) => panic!("Engine frame failed: {}", error),
+        }
+    }
+
+    /// Runs a single frame of the engine, like `engine_frame`.
+    /// If a state or the post update function returns an error, the frame stops there.
+    /// The error policy is then applied and the error is returned.
+    ///
+    /// When panic isolation is enabled, a panicking frame doesn't return an error.
+    /// It is reported to the panic handler instead.
+    pub fn try_engine_frame(&mut self, sleep: bool) -> Result<bool, StateError> {
+        let result = if self.panic_isolation.is_some() {
+            match std::panic::catch_unwind(AssertUnwindSafe(|| self.run_frame(sleep))) {
+                Ok(result) => result,
+                Err(payload) => {
+                    let running = self.recover_from_panic(payload);
+                    #[cfg(feature = "chrome_trace")]
+                    self.write_chrome_transitions(self.clock.now());
+                    return Ok(running);
+                }
+            }
+        } else {
+            self.run_frame(sleep)
+        };
+        if let Err(_error) = &result {
+            #[cfg(feature = "tracing")]
+            tracing::error!(error = %_error, "engine frame failed");
+            let failed_state = self.state_machine.take_failed_state();
+            match self.error_policy {
+                ErrorPolicy::PopState => {
+                    if let Some(index) = failed_state {
+                        self.state_machine.pop_from(index, &mut self.state_data);
+                    }
+                }
+                ErrorPolicy::Quit => self.state_machine.stop(&mut self.state_data),
+                ErrorPolicy::Continue => {}
+            }
+            #[cfg(feature = "chrome_trace")]
+            self.write_chrome_transitions(self.clock.now());
+        }
+        result
+    }
+
+    fn recover_from_panic(&mut self, payload: Box<dyn std::any::Any + Send>) -> bool {
+        let report = PanicReport {
+            message: panic_message(payload.as_ref()),
+            frame_number: self.time.frame_number(),
+            state_names: self
+                .state_machine
+                .states()
+                .map(|state| state.name().to_string())
+                .collect(),
+            recent_frame_times: self.frame_stats.frame_times().copied().collect(),
+            recent_frames: self
+                .profiler
+                .as_ref()
+                .map(|profiler| profiler.frames().copied().collect())
+                .unwrap_or_default(),
+        };
+        let isolation = self
+            .panic_isolation
+            .as_mut()
+            .expect("Panic isolation is enabled when recovering from panics.");
+        (isolation.handler)(&report);
+        let panicked_state = self.state_machine.take_failed_state();
+        match &isolation.policy {
+            PanicPolicy::PopState => {
+                if let Some(index) = panicked_state {
+                    self.state_machine.pop_from(index, &mut self.state_data);
+                }
+            }
+            PanicPolicy::Fallback(fallback) => {
+                let state = fallback();
+                self.state_machine.stop(&mut self.state_data);
+                self.state_machine.push(state, &mut self.state_data);
+            }
+            PanicPolicy::Abort => std::panic::resume_unwind(payload),
+        }
+        self.state_machine.is_running()
+    }
+
+    fn run_frame(&mut self, sleep: bool) -> Result<bool, StateError> {
+        if self.quit_handle.is_quit_requested() {
+            self.state_machine.stop(&mut self.state_data);
+            self.quit_handle.clear();
+            #[cfg(feature = "chrome_trace")]
+            self.write_chrome_transitions(self.clock.now());
+            return Ok(false);
+        }
+        let frame_start = self.clock.now();
         if sleep {
-            let delta = self.loop_helper.loop_start();
+            let delta = frame_start - self.last_frame_start;
+            self.last_frame_start = frame_start;
             {
                 self.time.advance_frame(delta);
             }
+            self.frame_stats.record(delta);
         }
-
-        self.state_machine.update(&mut self.state_data);
+        #[cfg(feature = "tracing")]
+        let _frame_span =
+            tracing::info_span!("engine_frame", frame = self.time.frame_number()).entered();
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("pre_update").entered();
+            self.hooks
+                .run(FrameStage::PreUpdate, &mut self.state_data, &self.time)?;
+            self.state_machine.apply_commands(&mut self.state_data);
+            for event in self.events.drain() {
+                self.state_machine
+                    .handle_event(&mut self.state_data, event.as_ref());
+            }
+        }
+        let pre_update_end = self.clock.now();
         if sleep {
-            self.loop_helper.loop_sleep();
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("fixed_update").entered();
+            self.fixed_update()?;
         }
-        (self.post_update)(&mut self.state_data, &self.time);
-        self.state_machine.is_running()
+        let fixed_update_end = self.clock.now();
+
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("update").entered();
+            self.state_machine.try_update(&mut self.state_data)?;
+            self.hooks
+                .run(FrameStage::Update, &mut self.state_data, &self.time)?;
+        }
+        let update_end = self.clock.now();
+        if sleep && self.clock.is_real_time() {
+            if let Some(target_delta) = self.target_delta {
+                let elapsed = update_end - self.last_frame_start;
+                if elapsed < target_delta {
+                    self.pacer.wait(target_delta - elapsed);
+                }
+            }
+        }
+        let sleep_end = self.clock.now();
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("post_update").entered();
+            (self.post_update)(&mut self.state_data, &self.time).into_result()?;
+            self.hooks
+                .run(FrameStage::PostUpdate, &mut self.state_data, &self.time)?;
+        }
+        let post_update_end = self.clock.now();
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("render").entered();
+            self.state_machine
+                .render(&mut self.state_data, self.time.interpolation_alpha());
+            self.hooks
+                .run(FrameStage::Render, &mut self.state_data, &self.time)?;
+        }
+        let render_end = self.clock.now();
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("end_frame").entered();
+            self.hooks
+                .run(FrameStage::EndFrame, &mut self.state_data, &self.time)?;
+        }
+        let end_frame_end = self.clock.now();
+
+        let profile = FrameProfile {
+            frame_number: self.time.frame_number(),
+            pre_update: pre_update_end - frame_start,
+            fixed_update: fixed_update_end - pre_update_end,
+            update: update_end - fixed_update_end,
+            sleep: sleep_end - update_end,
+            post_update: post_update_end - sleep_end,
+            render: render_end - post_update_end,
+            end_frame: end_frame_end - render_end,
+        };
+        #[cfg(feature = "chrome_trace")]
+        self.write_chrome_trace(frame_start, update_end, &profile);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(profile);
+        }
+        Ok(self.state_machine.is_running())
+    }
+
+    #[cfg(feature = "chrome_trace")]
+    fn write_chrome_trace(
+        &mut self,
+        frame_start: Duration,
+        update_end: Duration,
+        profile: &FrameProfile,
+    ) {
+        if let Some(tracer) = self.chrome_tracer.as_mut() {
+            if tracer.write_frame(frame_start, profile).is_err() {
+                self.stop_chrome_trace();
+                return;
+            }
+        }
+        self.write_chrome_transitions(update_end);
+    }
+
+    /// Writes the transitions recorded since the last write, at the given time.
+    /// Called after writing a frame and on the paths leaving a frame early.
+    #[cfg(feature = "chrome_trace")]
+    fn write_chrome_transitions(&mut self, time: Duration) {
+        if let Some(tracer) = self.chrome_tracer.as_mut() {
+            let result = self
+                .state_machine
+                .drain_transitions()
+                .into_iter()
+                .try_for_each(|kind| tracer.write_transition(time, kind));
+            if result.is_err() {
+                self.stop_chrome_trace();
+            }
+        }
+    }
+
+    fn fixed_update(&mut self) -> Result<(), StateError> {
+        if self.time.fixed_time() == Duration::from_secs(0) {
+            return Ok(());
+        }
+        let mut steps = 0;
+        while self.time.step_fixed_update() {
+            if steps >= self.max_fixed_steps || !self.state_machine.is_running() {
+                // Drop the time we can't catch up on.
+                while self.time.step_fixed_update() {}
+                break;
+            }
+            self.state_machine.try_fixed_update(&mut self.state_data)?;
+            self.hooks
+                .run(FrameStage::FixedUpdate, &mut self.state_data, &self.time)?;
+            steps += 1;
+        }
+        Ok(())
     }
 
     /// Runs the engine until the state machine quits.
     /// Generics:
     /// - SD: The type of the data that is passed to states when updating.
     /// - I: The type of the initial state. This is the first state that it started
-    /// when the engine is started.
+    ///   when the engine is started.
     /// - F: The post update function. This function is called after each loop of
-    /// of the engine. It receives the state data mutable and a reference to the
-    /// structure keeping track of the time. This function is called *after* sleeping
-    /// at the end of the frame, which means it is equivalent to the start of the next
-    /// frame.
+    ///   of the engine. It receives the state data mutable and a reference to the
+    ///   structure keeping track of the time. This function is called *after* sleeping
+    ///   at the end of the frame, which means it is equivalent to the start of the next
+    ///   frame. `Time::interpolation_alpha` can be used to blend between fixed updates.
     pub fn engine_loop(&mut self) {
         while self.engine_frame(true) {}
     }
+
+    /// Runs the engine until the state machine quits or until an error is returned
+    /// by a state or by the post update function.
+    /// See `try_engine_frame` for how errors are handled.
+    pub fn try_engine_loop(&mut self) -> Result<(), StateError> {
+        while self.try_engine_frame(true)? {}
+        Ok(())
+    }
+}
+
+impl<SD, F: FnMut(&mut SD, &Time) -> R + 'static, C: ClockSource, R: HookResult>
+    Engine<SD, F, C, R>
+{
+    /// Boxes the post update function, erasing its type.
+    /// The engine keeps its states, data and configuration.
+    pub fn boxed(self) -> Engine<SD, BoxedPostUpdate<SD, R>, C, R> {
+        self.map_post_update(|post_update| Box::new(post_update) as BoxedPostUpdate<SD, R>)
+    }
+}
+
+/// Returns the duration of a frame at the given framerate, or `None` if it is uncapped.
+pub(crate) fn target_delta(max_fps: f32) -> Result<Option<Duration>, BuildError> {
+    if max_fps == f32::INFINITY {
+        Ok(None)
+    } else if max_fps > 0.0 {
+        Duration::try_from_secs_f64(1.0 / max_fps as f64)
+            .map(Some)
+            .map_err(|_| BuildError::InvalidMaxFps(max_fps))
+    } else {
+        Err(BuildError::InvalidMaxFps(max_fps))
+    }
 }
 
 #[cfg(test)]
@@ -120,4 +699,440 @@ mod tests {
         )
         .engine_loop();
     }
+
+    #[test]
+    fn test_max_fixed_steps() {
+        struct MyState;
+        impl State<(u32, u32)> for MyState {
+            fn fixed_update(&mut self, state_data: &mut (u32, u32)) -> StateTransition<(u32, u32)> {
+                state_data.0 += 1;
+                StateTransition::None
+            }
+            fn update(&mut self, state_data: &mut (u32, u32)) -> StateTransition<(u32, u32)> {
+                state_data.1 += 1;
+                if state_data.1 == 2 {
+                    StateTransition::Quit
+                } else {
+                    StateTransition::None
+                }
+            }
+        }
+        let mut engine = Engine::new(MyState, (0, 0), |_, _| {}, 1000.0);
+        engine.time.set_fixed_time(Duration::from_nanos(1));
+        engine.set_max_fixed_steps(3);
+        engine.engine_loop();
+        assert_eq!(engine.state_data, (6, 2));
+    }
+
+    #[test]
+    fn test_pacer() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        struct RecordPacer(Rc<Cell<Duration>>);
+        impl FramePacer for RecordPacer {
+            fn wait(&mut self, remaining: Duration) {
+                self.0.set(remaining);
+            }
+        }
+        struct MyState;
+        impl State<()> for MyState {
+            fn update(&mut self, _: &mut ()) -> StateTransition<()> {
+                StateTransition::Quit
+            }
+        }
+        let waited = Rc::new(Cell::new(Duration::from_secs(0)));
+        let mut engine = Engine::new(MyState, (), |_, _| {}, 1.0);
+        engine.set_pacer(RecordPacer(waited.clone()));
+        engine.engine_loop();
+        assert!(waited.get() > Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_profiler() {
+        struct MyState;
+        impl State<()> for MyState {}
+        let mut engine = Engine::new(MyState, (), |_, _| {}, 1000.0);
+        engine.enable_profiler(2);
+        for _ in 0..3 {
+            engine.engine_frame(true);
+        }
+        let profiler = engine.disable_profiler().unwrap();
+        let frames = profiler
+            .frames()
+            .map(|f| f.frame_number)
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec![2, 3]);
+        assert!(profiler.last().unwrap().sleep > Duration::from_micros(100));
+        assert!(engine.profiler().is_none());
+    }
+
+    #[test]
+    fn test_max_fps() {
+        struct MyState;
+        impl State<()> for MyState {}
+        let mut engine = Engine::new(MyState, (), |_, _| {}, 50.0);
+        assert!((engine.max_fps().unwrap() - 50.0).abs() < 0.01);
+        engine.set_max_fps(100.0);
+        assert!((engine.max_fps().unwrap() - 100.0).abs() < 0.01);
+        engine.set_uncapped();
+        assert_eq!(engine.max_fps(), None);
+        engine.set_max_fps(f32::INFINITY);
+        assert_eq!(engine.max_fps(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        struct Failing;
+        impl State<u32> for Failing {
+            fn try_update(
+                &mut self,
+                state_data: &mut u32,
+            ) -> Result<StateTransition<u32>, StateError> {
+                *state_data += 1;
+                Err("failed".into())
+            }
+        }
+        let mut engine = Engine::new(Failing, 0, |_, _| {}, f32::INFINITY);
+        assert!(engine.try_engine_loop().is_err());
+        assert!(engine.state_machine.is_running());
+        engine.set_error_policy(ErrorPolicy::PopState);
+        assert_eq!(
+            engine.try_engine_frame(false).unwrap_err().to_string(),
+            "failed"
+        );
+        assert!(!engine.state_machine.is_running());
+        assert_eq!(engine.state_data, 2);
+
+        struct MyState;
+        impl State<u32> for MyState {}
+        struct Overlay;
+        impl State<u32> for Overlay {
+            fn update_below(&self) -> bool {
+                true
+            }
+        }
+        let mut engine = Engine::new(MyState, 0, |_, _| {}, f32::INFINITY);
+        engine
+            .state_machine
+            .push(Box::new(Failing), &mut engine.state_data);
+        engine
+            .state_machine
+            .push(Box::new(Overlay), &mut engine.state_data);
+        engine.set_error_policy(ErrorPolicy::PopState);
+        assert!(engine.try_engine_frame(false).is_err());
+        assert_eq!(engine.state_machine.depth(), 1);
+        engine.add_hook(FrameStage::Update, |_, _| Err("hook failed"));
+        assert!(engine.try_engine_frame(false).is_err());
+        assert_eq!(engine.state_machine.depth(), 1);
+        let mut engine = Engine::new(
+            MyState,
+            0,
+            |s, _| {
+                if *s == 2 {
+                    Err("post update failed")
+                } else {
+                    *s += 1;
+                    Ok(())
+                }
+            },
+            f32::INFINITY,
+        );
+        engine.set_error_policy(ErrorPolicy::Quit);
+        assert!(engine.try_engine_loop().is_err());
+        assert!(!engine.state_machine.is_running());
+    }
+
+    #[test]
+    fn test_panic_isolation() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        struct Panicking;
+        impl State<u32> for Panicking {
+            fn update(&mut self, state_data: &mut u32) -> StateTransition<u32> {
+                *state_data += 1;
+                panic!("state panicked");
+            }
+        }
+        struct Fallback;
+        impl State<u32> for Fallback {
+            fn update(&mut self, _: &mut u32) -> StateTransition<u32> {
+                StateTransition::Quit
+            }
+        }
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let handler_reports = reports.clone();
+        let mut engine = Engine::new(Panicking, 0, |_, _| {}, f32::INFINITY);
+        engine.enable_panic_isolation(
+            PanicPolicy::Fallback(Box::new(|| Box::new(Fallback))),
+            move |report: &PanicReport| handler_reports.borrow_mut().push(report.clone()),
+        );
+        engine.engine_loop();
+        assert_eq!(engine.state_data, 1);
+        let reports = reports.borrow();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].message.as_deref(), Some("state panicked"));
+        assert_eq!(reports[0].frame_number, 1);
+        assert_eq!(
+            reports[0].state_names,
+            vec!["game_engine_core::tests::test_panic_isolation::Panicking"]
+        );
+
+        let mut engine = Engine::new(Panicking, 0, |_, _| {}, f32::INFINITY);
+        engine.enable_panic_isolation(PanicPolicy::PopState, |_| {});
+        assert!(!engine.engine_frame(true));
+
+        struct Overlay;
+        impl State<u32> for Overlay {
+            fn update_below(&self) -> bool {
+                true
+            }
+        }
+        let mut engine = Engine::new(Fallback, 0, |_, _| {}, f32::INFINITY);
+        engine
+            .state_machine
+            .push(Box::new(Panicking), &mut engine.state_data);
+        engine
+            .state_machine
+            .push(Box::new(Overlay), &mut engine.state_data);
+        engine.enable_panic_isolation(PanicPolicy::PopState, |_| {});
+        assert!(engine.engine_frame(false));
+        assert_eq!(engine.state_machine.depth(), 1);
+        engine.add_hook(FrameStage::PreUpdate, |_, _| -> () {
+            panic!("hook panicked")
+        });
+        assert!(engine.engine_frame(false));
+        assert_eq!(engine.state_machine.depth(), 1);
+    }
+
+    #[test]
+    fn test_events() {
+        struct Menu;
+        impl State<Vec<String>> for Menu {
+            fn handle_event(&mut self, s: &mut Vec<String>, event: &dyn Any) -> EventResponse {
+                match event.downcast_ref::<&str>() {
+                    Some(key) => {
+                        s.push(format!("menu {}", key));
+                        EventResponse::Consumed
+                    }
+                    None => EventResponse::Ignored,
+                }
+            }
+        }
+        struct Game;
+        impl State<Vec<String>> for Game {
+            fn handle_event(&mut self, s: &mut Vec<String>, event: &dyn Any) -> EventResponse {
+                s.push(format!("game {:?}", event.downcast_ref::<u32>()));
+                EventResponse::Consumed
+            }
+        }
+        let mut engine = Engine::new(Game, vec![], |_, _| {}, f32::INFINITY);
+        engine
+            .state_machine
+            .push(Box::new(Menu), &mut engine.state_data);
+        engine.send_event("escape");
+        let sender = engine.event_sender();
+        std::thread::spawn(move || sender.send(7u32))
+            .join()
+            .unwrap();
+        engine.engine_frame(false);
+        assert_eq!(engine.state_data, vec!["menu escape", "game Some(7)"]);
+        engine.engine_frame(false);
+        assert_eq!(engine.state_data.len(), 2);
+    }
+
+    #[cfg(feature = "chrome_trace")]
+    #[test]
+    fn test_chrome_trace_quit() {
+        struct MyState;
+        impl State<()> for MyState {}
+        let path = std::env::temp_dir().join(format!(
+            "game_engine_core_trace_{}.json",
+            std::process::id()
+        ));
+        let mut engine = Engine::new(MyState, (), |_, _| {}, f32::INFINITY);
+        engine.start_chrome_trace(&path).unwrap();
+        engine.quit_handle().request_quit();
+        assert!(!engine.engine_frame(false));
+        engine.stop_chrome_trace();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(json.contains(r#""name":"Quit","cat":"state_transition""#));
+    }
+
+    #[test]
+    fn test_quit_handle() {
+        struct MyState(u32);
+        impl State<Vec<u32>> for MyState {
+            fn on_stop(&mut self, state_data: &mut Vec<u32>) {
+                state_data.push(self.0);
+            }
+            fn update(&mut self, _: &mut Vec<u32>) -> StateTransition<Vec<u32>> {
+                if self.0 == 1 {
+                    StateTransition::Push(Box::new(MyState(2)))
+                } else {
+                    StateTransition::None
+                }
+            }
+        }
+        let mut engine = Engine::new(MyState(1), vec![], |_, _| {}, f32::INFINITY);
+        let quit_handle = engine.quit_handle();
+        assert!(engine.engine_frame(true));
+        std::thread::spawn(move || quit_handle.request_quit())
+            .join()
+            .unwrap();
+        assert!(!engine.engine_frame(true));
+        assert_eq!(engine.state_data, vec![2, 1]);
+        assert!(!engine.quit_handle().is_quit_requested());
+
+        engine
+            .state_machine
+            .push(Box::new(MyState(3)), &mut engine.state_data);
+        assert!(engine.engine_frame(true));
+    }
+
+    #[test]
+    fn test_hooks() {
+        struct MyState;
+        impl State<Vec<&'static str>> for MyState {
+            fn fixed_update(
+                &mut self,
+                s: &mut Vec<&'static str>,
+            ) -> StateTransition<Vec<&'static str>> {
+                s.push("fixed state");
+                StateTransition::None
+            }
+            fn update(&mut self, s: &mut Vec<&'static str>) -> StateTransition<Vec<&'static str>> {
+                s.push("update state");
+                StateTransition::None
+            }
+        }
+        let mut engine = Engine::with_clock(
+            MyState,
+            vec![],
+            |s, _| s.push("post update"),
+            f32::INFINITY,
+            ManualClock::default(),
+        );
+        engine.time.set_fixed_time(Duration::from_millis(10));
+        for stage in FrameStage::ALL.iter().rev() {
+            let name = match stage {
+                FrameStage::PreUpdate => "pre",
+                FrameStage::FixedUpdate => "fixed",
+                FrameStage::Update => "update",
+                FrameStage::PostUpdate => "post",
+                FrameStage::Render => "render",
+                FrameStage::EndFrame => "end",
+            };
+            engine.add_hook(*stage, move |s, _| s.push(name));
+        }
+        let first = engine.add_hook_ordered(FrameStage::EndFrame, -1, |s, _| s.push("first"));
+        engine.clock.advance(Duration::from_millis(10));
+        engine.engine_frame(true);
+        assert_eq!(
+            engine.state_data,
+            vec![
+                "pre",
+                "fixed state",
+                "fixed",
+                "update state",
+                "update",
+                "post update",
+                "post",
+                "render",
+                "first",
+                "end"
+            ]
+        );
+        assert!(engine.remove_hook(first));
+        engine.state_data.clear();
+        engine.engine_frame(true);
+        assert_eq!(engine.state_data.last(), Some(&"end"));
+        assert!(!engine.state_data.contains(&"first"));
+    }
+
+    #[test]
+    fn test_boxed() {
+        struct MyState;
+        impl State<i32> for MyState {
+            fn update(&mut self, state_data: &mut i32) -> StateTransition<i32> {
+                *state_data += 1;
+                StateTransition::Quit
+            }
+        }
+        struct App {
+            engine: Engine<i32>,
+        }
+        fn create_engine(offset: i32) -> Engine<i32> {
+            Engine::new(MyState, 0, move |s, _| *s += offset, f32::INFINITY).boxed()
+        }
+        let mut app = App {
+            engine: create_engine(10),
+        };
+        app.engine.engine_loop();
+        assert_eq!(app.engine.state_data, 11);
+
+        type PostUpdateResult = Result<(), StateError>;
+        type FallibleEngine =
+            Engine<i32, BoxedPostUpdate<i32, PostUpdateResult>, RealTimeClock, PostUpdateResult>;
+        let mut engine: FallibleEngine = Engine::new(
+            MyState,
+            0,
+            |_: &mut i32, _: &Time| -> Result<(), StateError> { Err("post update".into()) },
+            f32::INFINITY,
+        )
+        .boxed();
+        assert!(engine.try_engine_frame(false).is_err());
+    }
+
+    #[test]
+    fn test_stateful_hooks() {
+        struct MyState;
+        impl State<Vec<u32>> for MyState {
+            fn update(&mut self, _: &mut Vec<u32>) -> StateTransition<Vec<u32>> {
+                StateTransition::None
+            }
+        }
+        let mut frames = 0;
+        let mut engine = Engine::new(
+            MyState,
+            vec![],
+            move |s: &mut Vec<u32>, _: &Time| {
+                frames += 1;
+                s.push(frames);
+            },
+            f32::INFINITY,
+        );
+        let mut total = 0;
+        engine.add_hook(FrameStage::EndFrame, move |s, _| {
+            total += s.last().unwrap();
+            s.push(total);
+        });
+        engine.engine_frame(false);
+        engine.engine_frame(false);
+        assert_eq!(engine.state_data, vec![1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        struct MyState;
+        impl State<u32> for MyState {
+            fn fixed_update(&mut self, state_data: &mut u32) -> StateTransition<u32> {
+                *state_data += 1;
+                StateTransition::None
+            }
+        }
+        let mut engine = Engine::with_clock(MyState, 0, |_, _| {}, 1.0, ManualClock::default());
+        engine.time.set_fixed_time(Duration::from_millis(10));
+        for _ in 0..10 {
+            engine.clock.advance(Duration::from_millis(15));
+            engine.engine_frame(true);
+        }
+        assert_eq!(engine.state_data, 15);
+        assert_eq!(engine.time.frame_number(), 10);
+        assert_eq!(engine.time.absolute_time(), Duration::from_millis(150));
+        assert_eq!(
+            engine.frame_stats().average(),
+            Some(Duration::from_millis(15))
+        );
+    }
 }
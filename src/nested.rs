@@ -0,0 +1,217 @@
+//! States containing their own state machine, used to build hierarchical state machines.
+
+use crate::{EventResponse, State, StateError, StateMachine, StateTransition};
+use std::any::Any;
+
+/// The function creating the exit transition of a `NestedState`.
+/// It receives the state data and the result of the last child state, if any.
+type ExitFn<S> = dyn FnMut(&mut S, Option<Box<dyn Any>>) -> StateTransition<S>;
+
+/// A state owning a child `StateMachine`.
+/// The updates, fixed updates, renders, events, pauses and resumes it receives are
+/// forwarded to the child machine, which starts with the initial state when this state
+/// starts and is stopped when this state stops. Commands sent to the child machine are
+/// applied before each of its updates and fixed updates.
+/// When the child machine has no states left, this state performs its exit transition.
+/// By default, it pops this state, giving the parent the result of the last child state
+/// if it was popped using `StateTransition::PopWith`.
+pub struct NestedState<S> {
+    machine: StateMachine<S>,
+    initial_state: Option<Box<dyn State<S>>>,
+    on_exit: Box<ExitFn<S>>,
+}
+
+impl<S> NestedState<S> {
+    /// Creates a new `NestedState` whose child machine starts with the given state.
+    pub fn new<I: State<S> + 'static>(initial_state: I) -> Self {
+        Self {
+            machine: StateMachine::default(),
+            initial_state: Some(Box::new(initial_state)),
+            on_exit: Box::new(|_, result| match result {
+                Some(result) => StateTransition::PopWith(result),
+                None => StateTransition::Pop,
+            }),
+        }
+    }
+
+    /// Sets the function creating the transition performed in the parent machine when
+    /// the child machine has no states left. It receives the state data and the result
+    /// of the last child state, if it was popped using `StateTransition::PopWith`.
+    pub fn with_exit<E>(mut self, on_exit: E) -> Self
+    where
+        E: FnMut(&mut S, Option<Box<dyn Any>>) -> StateTransition<S> + 'static,
+    {
+        self.on_exit = Box::new(on_exit);
+        self
+    }
+
+    /// Returns the child state machine.
+    pub fn child(&self) -> &StateMachine<S> {
+        &self.machine
+    }
+
+    /// Returns the child state machine mutably.
+    pub fn child_mut(&mut self) -> &mut StateMachine<S> {
+        &mut self.machine
+    }
+
+    fn exit_transition(&mut self, state_data: &mut S) -> StateTransition<S> {
+        if self.machine.is_running() {
+            StateTransition::None
+        } else {
+            let result = self.machine.take_exit_result();
+            (self.on_exit)(state_data, result)
+        }
+    }
+}
+
+impl<S> State<S> for NestedState<S> {
+    fn on_start(&mut self, state_data: &mut S) {
+        if let Some(state) = self.initial_state.take() {
+            self.machine.push(state, state_data);
+        }
+    }
+
+    fn on_stop(&mut self, state_data: &mut S) {
+        self.machine.stop(state_data);
+    }
+
+    fn on_pause(&mut self, state_data: &mut S) {
+        self.machine.pause_top(state_data);
+    }
+
+    fn on_resume(&mut self, state_data: &mut S) {
+        self.machine.resume_top(None, state_data);
+    }
+
+    fn on_resume_with(&mut self, state_data: &mut S, result: Box<dyn Any>) {
+        self.machine.resume_top(Some(result), state_data);
+    }
+
+    fn try_update(&mut self, state_data: &mut S) -> Result<StateTransition<S>, StateError> {
+        self.machine.apply_commands(state_data);
+        self.machine.try_update(state_data)?;
+        Ok(self.exit_transition(state_data))
+    }
+
+    fn try_fixed_update(&mut self, state_data: &mut S) -> Result<StateTransition<S>, StateError> {
+        self.machine.apply_commands(state_data);
+        self.machine.try_fixed_update(state_data)?;
+        Ok(self.exit_transition(state_data))
+    }
+
+    fn render(&mut self, state_data: &mut S, alpha: f32) {
+        self.machine.render(state_data, alpha);
+    }
+
+    fn handle_event(&mut self, state_data: &mut S, event: &dyn Any) -> EventResponse {
+        self.machine.handle_event(state_data, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::any::Any;
+
+    struct Child(&'static str);
+
+    impl State<Vec<String>> for Child {
+        fn on_pause(&mut self, data: &mut Vec<String>) {
+            data.push(format!("pause {}", self.0));
+        }
+        fn on_resume(&mut self, data: &mut Vec<String>) {
+            data.push(format!("resume {}", self.0));
+        }
+        fn update(&mut self, data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            data.push(format!("update {}", self.0));
+            match self.0 {
+                "walk" => StateTransition::Push(Box::new(Child("jump"))),
+                _ => StateTransition::Quit,
+            }
+        }
+        fn handle_event(&mut self, data: &mut Vec<String>, event: &dyn Any) -> EventResponse {
+            data.push(format!(
+                "event {} {:?}",
+                self.0,
+                event.downcast_ref::<u32>()
+            ));
+            EventResponse::Consumed
+        }
+    }
+
+    struct Root;
+
+    impl State<Vec<String>> for Root {
+        fn on_resume(&mut self, data: &mut Vec<String>) {
+            data.push("resume root".to_string());
+        }
+    }
+
+    #[test]
+    fn nested_state() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Root), &mut data);
+        sm.push(Box::new(NestedState::new(Child("walk"))), &mut data);
+        sm.update(&mut data);
+        assert_eq!(sm.handle_event(&mut data, &1u32), EventResponse::Consumed);
+        sm.push(Box::new(Root), &mut data);
+        sm.pop(&mut data);
+        sm.update(&mut data);
+        assert_eq!(
+            data,
+            vec![
+                "update walk",
+                "pause walk",
+                "event jump Some(1)",
+                "pause jump",
+                "resume jump",
+                "update jump",
+                "resume root",
+            ]
+        );
+        assert_eq!(sm.depth(), 1);
+    }
+
+    struct Picker;
+
+    impl State<Vec<String>> for Picker {
+        fn fixed_update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            StateTransition::pop_with(5u32)
+        }
+    }
+
+    struct Receiver;
+
+    impl State<Vec<String>> for Receiver {
+        fn on_resume_with(&mut self, data: &mut Vec<String>, result: Box<dyn Any>) {
+            data.push(format!("result {:?}", result.downcast::<u32>().ok()));
+        }
+    }
+
+    #[test]
+    fn nested_exit_result() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        let mut nested = NestedState::new(Root);
+        let commands = nested.child_mut().command_sender();
+        sm.push(Box::new(Receiver), &mut data);
+        sm.push(Box::new(nested), &mut data);
+        commands.switch(Picker);
+        sm.fixed_update(&mut data);
+        assert_eq!(data, vec!["result Some(5)"]);
+        assert_eq!(sm.depth(), 1);
+
+        let nested = NestedState::new(Picker).with_exit(|data: &mut Vec<String>, result| {
+            data.push(format!("exit {}", result.is_some()));
+            StateTransition::Quit
+        });
+        sm.push(Box::new(nested), &mut data);
+        sm.fixed_update(&mut data);
+        assert_eq!(data[1..], ["exit true"]);
+        assert!(!sm.is_running());
+    }
+}
@@ -0,0 +1,106 @@
+//! Strategies used to wait between frames when the engine runs faster than its
+//! target framerate.
+
+use spin_sleep::SpinSleeper;
+use std::time::{Duration, Instant};
+
+/// A strategy used to wait until the next frame should start.
+pub trait FramePacer {
+    /// Waits for the given duration, which is the time left before the next frame.
+    fn wait(&mut self, remaining: Duration);
+}
+
+/// Sleeps using the `spin_sleep` crate: the thread sleeps natively, then spins
+/// for the last part of the wait to be accurate.
+/// This is the pacer used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpinSleepPacer {
+    sleeper: SpinSleeper,
+}
+
+impl FramePacer for SpinSleepPacer {
+    fn wait(&mut self, remaining: Duration) {
+        self.sleeper.sleep(remaining);
+    }
+}
+
+/// Sleeps using `std::thread::sleep`.
+/// It barely uses any CPU, but the frame timing will be less accurate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeSleepPacer;
+
+impl FramePacer for NativeSleepPacer {
+    fn wait(&mut self, remaining: Duration) {
+        std::thread::sleep(remaining);
+    }
+}
+
+/// Sleeps natively until `spin_threshold` is left, then spins for the rest of the wait.
+/// A higher threshold is more accurate but uses more CPU.
+#[derive(Clone, Copy, Debug)]
+pub struct HybridPacer {
+    /// The time spent spinning at the end of the wait.
+    pub spin_threshold: Duration,
+}
+
+impl HybridPacer {
+    /// Creates a new `HybridPacer` spinning for the given duration at the end of waits.
+    pub fn new(spin_threshold: Duration) -> Self {
+        Self { spin_threshold }
+    }
+}
+
+impl FramePacer for HybridPacer {
+    fn wait(&mut self, remaining: Duration) {
+        let deadline = Instant::now() + remaining;
+        if remaining > self.spin_threshold {
+            std::thread::sleep(remaining - self.spin_threshold);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Yields the thread to the OS scheduler until the time is up, without ever sleeping.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YieldPacer;
+
+impl FramePacer for YieldPacer {
+    fn wait(&mut self, remaining: Duration) {
+        let deadline = Instant::now() + remaining;
+        while Instant::now() < deadline {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Never waits. The engine runs as fast as it can.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnlimitedPacer;
+
+impl FramePacer for UnlimitedPacer {
+    fn wait(&mut self, _remaining: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn pacers_wait() {
+        let remaining = Duration::from_millis(5);
+        let mut pacers: Vec<Box<dyn FramePacer>> = vec![
+            Box::new(SpinSleepPacer::default()),
+            Box::new(NativeSleepPacer),
+            Box::new(HybridPacer::new(Duration::from_millis(1))),
+            Box::new(YieldPacer),
+        ];
+        for pacer in pacers.iter_mut() {
+            let start = Instant::now();
+            pacer.wait(remaining);
+            assert!(start.elapsed() >= remaining);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Recovery from panics happening during engine frames.
+
+use crate::{FrameProfile, State};
+use std::any::Any;
+use std::time::Duration;
+
+/// What the engine does after a frame panicked while panic isolation is enabled.
+pub enum PanicPolicy<SD> {
+    /// Pop the state that panicked, along with the states above it.
+    /// Panics in hooks or in the post update function leave the stack untouched.
+    PopState,
+    /// Stop all the states and push the state created by this function instead.
+    Fallback(Box<dyn Fn() -> Box<dyn State<SD>>>),
+    /// Resume the panic, ending the engine loop as if isolation was disabled.
+    Abort,
+}
+
+/// Information about a panic caught by the engine.
+#[derive(Clone, Debug)]
+pub struct PanicReport {
+    /// The panic message, if it was a string.
+    pub message: Option<String>,
+    /// The number of the frame that panicked, as given by `Time::frame_number`.
+    pub frame_number: u64,
+    /// The names of the states in the stack when the frame panicked, from the bottom
+    /// to the top.
+    pub state_names: Vec<String>,
+    /// The duration of the last frames, from the oldest to the most recent.
+    pub recent_frame_times: Vec<Duration>,
+    /// The profiles of the last frames, from the oldest to the most recent.
+    /// Empty unless the profiler is enabled.
+    pub recent_frames: Vec<FrameProfile>,
+}
+
+pub(crate) struct PanicIsolation<SD> {
+    pub(crate) policy: PanicPolicy<SD>,
+    pub(crate) handler: Box<dyn FnMut(&PanicReport)>,
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+}
@@ -0,0 +1,140 @@
+//! Instrumentation of the time spent in each phase of the engine frames.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The time spent in each phase of a single frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameProfile {
+    /// The frame number, as given by `Time::frame_number`.
+    pub frame_number: u64,
+    /// Time spent in the pre update hooks and delivering events.
+    pub pre_update: Duration,
+    /// Time spent running the fixed updates of states.
+    pub fixed_update: Duration,
+    /// Time spent running the update of states.
+    pub update: Duration,
+    /// Time spent waiting for the next frame.
+    pub sleep: Duration,
+    /// Time spent in the post update function.
+    pub post_update: Duration,
+    /// Time spent running the render of states.
+    pub render: Duration,
+    /// Time spent running the end of frame hooks.
+    pub end_frame: Duration,
+}
+
+impl FrameProfile {
+    /// Returns the total time spent in this frame.
+    pub fn total(&self) -> Duration {
+        self.pre_update
+            + self.fixed_update
+            + self.update
+            + self.sleep
+            + self.post_update
+            + self.render
+            + self.end_frame
+    }
+}
+
+/// Keeps the profiles of the last frames in a ring buffer.
+#[derive(Clone, Debug)]
+pub struct FrameProfiler {
+    capacity: usize,
+    frames: VecDeque<FrameProfile>,
+}
+
+impl FrameProfiler {
+    /// Creates a new `FrameProfiler` keeping the last `capacity` frames.
+    /// ## Panics
+    /// This will panic if capacity is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of frames kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Records the profile of a frame, dropping the oldest one if the buffer is full.
+    pub fn record(&mut self, profile: FrameProfile) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(profile);
+    }
+
+    /// Returns the recorded frames, from the oldest to the most recent.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.frames.iter()
+    }
+
+    /// Returns the most recent frame profile.
+    pub fn last(&self) -> Option<&FrameProfile> {
+        self.frames.back()
+    }
+
+    /// Forgets all the recorded frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Writes the recorded frames as CSV, with one line per frame.
+    /// Durations are written in microseconds.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "frame,pre_update_us,fixed_update_us,update_us,sleep_us,post_update_us,render_us,end_frame_us,total_us"
+        )?;
+        for frame in self.frames.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                frame.frame_number,
+                frame.pre_update.as_micros(),
+                frame.fixed_update.as_micros(),
+                frame.update.as_micros(),
+                frame.sleep.as_micros(),
+                frame.post_update.as_micros(),
+                frame.render.as_micros(),
+                frame.end_frame.as_micros(),
+                frame.total().as_micros(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn profiler_csv() {
+        let mut profiler = FrameProfiler::new(2);
+        for frame_number in 1..=3 {
+            profiler.record(FrameProfile {
+                frame_number,
+                update: Duration::from_micros(10),
+                sleep: Duration::from_micros(5),
+                end_frame: Duration::from_micros(2),
+                ..FrameProfile::default()
+            });
+        }
+        let mut csv = Vec::new();
+        profiler.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,pre_update_us,fixed_update_us,update_us,sleep_us,post_update_us,render_us,end_frame_us,total_us\n\
+             2,0,0,10,5,0,0,2,17\n\
+             3,0,0,10,5,0,0,2,17\n"
+        );
+    }
+}
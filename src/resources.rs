@@ -0,0 +1,255 @@
+//! A container holding at most one value of each type.
+//! It can be used as the state data, so that states and hooks only depend on the
+//! resources they use instead of on a single shared struct.
+
+use crate::ScopedData;
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A type map that can be used as the state data of an `Engine` or `StateMachine`.
+/// ```rust
+/// use game_engine_core::Resources;
+///
+/// struct Score(u32);
+///
+/// let mut resources = Resources::default();
+/// resources.insert(Score(0));
+/// resources.get_mut::<Score>().unwrap().0 += 10;
+/// assert_eq!(resources.fetch::<Score>().unwrap().0, 10);
+/// ```
+///
+/// When scopes are enabled in the state machine, states can insert resources using
+/// `insert_scoped` in `on_start`. They are removed when the state is popped.
+#[derive(Debug, Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    scopes: Vec<Vec<ScopedResource>>,
+}
+
+/// A resource inserted in a scope, with the resource it replaced.
+#[derive(Debug)]
+struct ScopedResource {
+    type_id: TypeId,
+    previous: Option<Box<dyn Any>>,
+}
+
+impl Resources {
+    /// Inserts a resource, returning the previous resource of the same type if there was one.
+    pub fn insert<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .map(|previous| *previous.downcast::<T>().unwrap())
+    }
+
+    /// Inserts a resource owned by the current scope. When the scope ends, the resource
+    /// is removed and the previous resource of the same type, if any, is restored.
+    /// Without a scope, this behaves like `insert`.
+    pub fn insert_scoped<T: 'static>(&mut self, resource: T) {
+        let previous = self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(ScopedResource {
+                type_id: TypeId::of::<T>(),
+                previous,
+            });
+        }
+    }
+
+    /// Removes the resource of the given type and returns it.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|resource| *resource.downcast::<T>().unwrap())
+    }
+
+    /// Returns whether there is a resource of the given type.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the resource of the given type.
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .map(|resource| resource.downcast_ref::<T>().unwrap())
+    }
+
+    /// Returns the resource of the given type mutably.
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .map(|resource| resource.downcast_mut::<T>().unwrap())
+    }
+
+    /// Returns the resource of the given type, or an error naming the missing type.
+    /// The error can be returned from fallible states and hooks using `?`.
+    pub fn fetch<T: 'static>(&self) -> Result<&T, MissingResource> {
+        self.get::<T>().ok_or_else(MissingResource::of::<T>)
+    }
+
+    /// Returns the resource of the given type mutably, or an error naming the missing type.
+    pub fn fetch_mut<T: 'static>(&mut self) -> Result<&mut T, MissingResource> {
+        self.get_mut::<T>().ok_or_else(MissingResource::of::<T>)
+    }
+
+    /// Returns the number of resources.
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    /// Returns whether there are no resources.
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+impl ScopedData for Resources {
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for scoped in scope.into_iter().rev() {
+                match scoped.previous {
+                    Some(previous) => self.resources.insert(scoped.type_id, previous),
+                    None => self.resources.remove(&scoped.type_id),
+                };
+            }
+        }
+    }
+}
+
+/// The error returned when fetching a resource that isn't in `Resources`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MissingResource {
+    /// The name of the type of the missing resource.
+    pub type_name: &'static str,
+}
+
+impl MissingResource {
+    fn of<T>() -> Self {
+        Self {
+            type_name: type_name::<T>(),
+        }
+    }
+}
+
+impl fmt::Display for MissingResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "missing resource of type `{}`", self.type_name)
+    }
+}
+
+impl std::error::Error for MissingResource {}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn resources() {
+        let mut resources = Resources::default();
+        assert_eq!(resources.insert(1u32), None);
+        assert_eq!(resources.insert(2u32), Some(1));
+        resources.insert("name");
+        *resources.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(resources.get::<u32>(), Some(&3));
+        assert_eq!(resources.len(), 2);
+
+        assert_eq!(resources.remove::<u32>(), Some(3));
+        assert!(!resources.contains::<u32>());
+        let error = resources.fetch::<u32>().unwrap_err();
+        assert_eq!(error.to_string(), "missing resource of type `u32`");
+    }
+
+    #[test]
+    fn resources_state_data() {
+        struct Counter(u32);
+        struct MyState;
+        impl State<Resources> for MyState {
+            fn update(&mut self, _: &mut Resources) -> StateTransition<Resources> {
+                StateTransition::None
+            }
+            fn try_update(
+                &mut self,
+                resources: &mut Resources,
+            ) -> Result<StateTransition<Resources>, StateError> {
+                resources.fetch_mut::<Counter>()?.0 += 1;
+                Ok(StateTransition::None)
+            }
+        }
+        let mut resources = Resources::default();
+        let mut sm = StateMachine::<Resources>::default();
+        sm.push(Box::new(MyState), &mut resources);
+        assert!(sm.try_update(&mut resources).is_err());
+        resources.insert(Counter(0));
+        sm.try_update(&mut resources).unwrap();
+        assert_eq!(resources.fetch::<Counter>().unwrap().0, 1);
+    }
+
+    #[test]
+    fn resources_scoped() {
+        struct Level(&'static str);
+        impl State<Resources> for Level {
+            fn on_start(&mut self, resources: &mut Resources) {
+                resources.insert_scoped(self.0);
+            }
+            fn update(&mut self, _: &mut Resources) -> StateTransition<Resources> {
+                match self.0 {
+                    "first" => StateTransition::Switch(Box::new(Level("third"))),
+                    _ => StateTransition::None,
+                }
+            }
+        }
+        let mut resources = Resources::default();
+        resources.insert("menu");
+        let mut sm = StateMachine::<Resources>::default();
+        sm.enable_scopes(&mut resources);
+        sm.push(Box::new(Level("first")), &mut resources);
+        sm.push(Box::new(Level("second")), &mut resources);
+        assert_eq!(resources.get::<&str>(), Some(&"second"));
+        sm.pop(&mut resources);
+        assert_eq!(resources.get::<&str>(), Some(&"first"));
+        sm.update(&mut resources);
+        assert_eq!(resources.get::<&str>(), Some(&"third"));
+        sm.stop(&mut resources);
+        assert_eq!(resources.get::<&str>(), Some(&"menu"));
+    }
+
+    #[test]
+    fn resources_scoped_panic() {
+        struct Panicking(&'static str);
+        impl State<Resources> for Panicking {
+            fn on_start(&mut self, resources: &mut Resources) {
+                resources.insert_scoped(self.0);
+                if self.0 == "start" {
+                    panic!("start failed");
+                }
+            }
+            fn on_stop(&mut self, _: &mut Resources) {
+                if self.0 == "stop" {
+                    panic!("stop failed");
+                }
+            }
+        }
+        let mut resources = Resources::default();
+        resources.insert("menu");
+        let mut sm = StateMachine::<Resources>::default();
+        sm.enable_scopes(&mut resources);
+        let started = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            sm.push(Box::new(Panicking("start")), &mut resources)
+        }));
+        assert!(started.is_err());
+        assert_eq!(sm.depth(), 1);
+        sm.pop(&mut resources);
+        assert_eq!(resources.get::<&str>(), Some(&"menu"));
+
+        sm.push(Box::new(Panicking("stop")), &mut resources);
+        let stopped =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sm.pop(&mut resources)));
+        assert!(stopped.is_err());
+        assert_eq!(sm.depth(), 0);
+        assert_eq!(resources.get::<&str>(), Some(&"menu"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Requests to stop the engine from outside of the states, such as from other
+//! threads or from signal handlers.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(all(feature = "signals", unix))]
+use std::sync::Mutex;
+
+/// A handle used to ask the engine to quit at the start of its next frame.
+/// When it does, all the states are stopped, from the top of the stack to the bottom,
+/// and the request is cleared, so that the engine can be started again by pushing a state.
+/// It can be cloned and sent to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct QuitHandle {
+    flag: Arc<AtomicBool>,
+    #[cfg(all(feature = "signals", unix))]
+    signal_actions: Arc<Mutex<SignalActions>>,
+}
+
+/// The actions registered for the termination signals.
+#[cfg(all(feature = "signals", unix))]
+#[derive(Debug, Default)]
+struct SignalActions {
+    /// Actions requesting the engine to quit.
+    quit: Vec<signal_hook::SigId>,
+    /// Actions exiting the process, standing in for the default handlers.
+    exit: Vec<signal_hook::SigId>,
+}
+
+#[cfg(all(feature = "signals", unix))]
+const TERMINATION_SIGNALS: [std::os::raw::c_int; 2] =
+    [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM];
+
+impl QuitHandle {
+    /// Asks the engine to quit at the start of its next frame.
+    pub fn request_quit(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether quitting was requested.
+    pub fn is_quit_requested(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /// Clears the quit request, once the engine has quit.
+    pub(crate) fn clear(&self) {
+        self.flag.store(false, Ordering::SeqCst);
+    }
+
+    /// Makes SIGINT and SIGTERM request the engine to quit, instead of killing the process.
+    /// If one of them arrives again before the engine quits, for example because a state
+    /// is stuck, the process exits with status 1.
+    /// Calling this again does nothing until `restore_termination_signals` is called.
+    #[cfg(all(feature = "signals", unix))]
+    pub fn quit_on_termination_signals(&self) -> std::io::Result<()> {
+        let mut actions = self.signal_actions.lock().unwrap();
+        if !actions.quit.is_empty() {
+            return Ok(());
+        }
+        for action in actions.exit.drain(..) {
+            signal_hook::low_level::unregister(action);
+        }
+        for signal in TERMINATION_SIGNALS {
+            // The shutdown must be registered first, so that it only exits on the signals
+            // arriving after the one requesting the engine to quit.
+            let shutdown =
+                signal_hook::flag::register_conditional_shutdown(signal, 1, self.flag.clone())?;
+            actions.quit.push(shutdown);
+            let quit = signal_hook::flag::register(signal, self.flag.clone())?;
+            actions.quit.push(quit);
+        }
+        Ok(())
+    }
+
+    /// Stops requesting the engine to quit on SIGINT and SIGTERM.
+    /// Since the original handlers can't be reinstalled, these signals then make the
+    /// process exit with status 1.
+    #[cfg(all(feature = "signals", unix))]
+    pub fn restore_termination_signals(&self) -> std::io::Result<()> {
+        let mut actions = self.signal_actions.lock().unwrap();
+        if actions.quit.is_empty() {
+            return Ok(());
+        }
+        for action in actions.quit.drain(..) {
+            signal_hook::low_level::unregister(action);
+        }
+        let always = Arc::new(AtomicBool::new(true));
+        for signal in TERMINATION_SIGNALS {
+            let exit = signal_hook::flag::register_conditional_shutdown(signal, 1, always.clone())?;
+            actions.exit.push(exit);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "signals", unix))]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn quit_on_termination_signals() {
+        let quit_handle = QuitHandle::default();
+        quit_handle.quit_on_termination_signals().unwrap();
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).unwrap();
+        assert!(quit_handle.is_quit_requested());
+        quit_handle.clear();
+        quit_handle.restore_termination_signals().unwrap();
+        assert!(!quit_handle.is_quit_requested());
+    }
+}
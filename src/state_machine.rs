@@ -0,0 +1,953 @@
+//! A generic stack-based state machine.
+//! This state machine contains a stack of states and handles transitions between them.
+//! StateTransition happen based on the return value of the currently running state's functions.
+//! Only the state at the top of the stack runs, unless it lets the states below it
+//! update or render, like overlays do.
+//!
+//! Originally published as the `game_state_machine` crate. `State` is a different trait
+//! than `game_state_machine::State`.
+
+use crate::commands::CommandQueue;
+use crate::{CommandSender, StateCommand};
+use std::any::{type_name, Any};
+use std::panic::{self, AssertUnwindSafe};
+
+/// An error returned by a fallible state or hook.
+/// Any error type implementing `std::error::Error` can be converted into it using `?`,
+/// and converted back using `downcast`.
+pub type StateError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A transition from one state to the other.
+/// ## Generics
+/// - S: State data, the data that is sent to states for them to do their operations.
+pub enum StateTransition<S> {
+    /// Stay in the current state.
+    None,
+    /// End the current state and go to the previous state on the stack, if any.
+    /// If we Pop the last state, the state machine exits.
+    Pop,
+    /// Like `Pop`, but gives a result to the state below using `State::on_resume_with`.
+    /// It is usually created using `StateTransition::pop_with`.
+    PopWith(Box<dyn Any>),
+    /// Push a new state on the stack.
+    Push(Box<dyn State<S>>),
+    /// Pop all states on the stack and insert this one.
+    Switch(Box<dyn State<S>>),
+    /// Pop all states and exit the state machine.
+    Quit,
+    /// Perform several transitions, in order, during the same update.
+    /// The states that are only exposed in the middle of the batch are neither resumed
+    /// nor paused again. The state at the top of the stack after the batch is resumed if
+    /// a state above it was popped, with the result of the last `PopWith` if it was the
+    /// last transition changing the stack.
+    Batch(Vec<StateTransition<S>>),
+}
+
+impl<S> StateTransition<S> {
+    /// Creates a transition popping the current state and giving `result` to the state
+    /// below it.
+    pub fn pop_with<T: Any>(result: T) -> Self {
+        StateTransition::PopWith(Box::new(result))
+    }
+}
+
+/// How to resume the state at the top of the stack after the state above it was popped.
+enum Resume {
+    Plain,
+    With(Box<dyn Any>),
+}
+
+/// The kind of a transition performed by the state machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// A state was pushed on the stack.
+    Push,
+    /// A state was popped from the stack.
+    Pop,
+    /// The state at the top of the stack was replaced.
+    Switch,
+    /// All states were removed from the stack.
+    Quit,
+}
+
+/// Whether a state consumed an event it received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventResponse {
+    /// The event is delivered to the state below, if any.
+    Ignored,
+    /// The event is not delivered to other states.
+    Consumed,
+}
+
+/// Trait that states must implement.
+///
+/// ## Generics
+/// - S: State data, the data that is sent to states for them to do their operations.
+pub trait State<S> {
+    /// Called when the state is first inserted on the stack.
+    fn on_start(&mut self, _state_data: &mut S) {}
+    /// Called when the state is popped from the stack.
+    fn on_stop(&mut self, _state_data: &mut S) {}
+    /// Called when a state is pushed over this one in the stack.
+    fn on_pause(&mut self, _state_data: &mut S) {}
+    /// Called when the state just on top of this one in the stack is popped.
+    fn on_resume(&mut self, _state_data: &mut S) {}
+    /// Called instead of `on_resume` when the state on top of this one is popped using
+    /// `StateTransition::PopWith`. The result can be downcasted to its original type.
+    /// Calls `on_resume` by default.
+    fn on_resume_with(&mut self, state_data: &mut S, _result: Box<dyn Any>) {
+        self.on_resume(state_data);
+    }
+    /// Executed on every frame immediately, as fast as the engine will allow.
+    /// If you need to execute logic at a predictable interval (for example, a physics engine)
+    /// use `fixed_update` instead.
+    fn update(&mut self, _state_data: &mut S) -> StateTransition<S> {
+        StateTransition::None
+    }
+    /// Executed zero or more times per frame, at the fixed interval given by
+    /// `Time::fixed_time`. It runs before `update`.
+    fn fixed_update(&mut self, _state_data: &mut S) -> StateTransition<S> {
+        StateTransition::None
+    }
+    /// Fallible version of `update`. The state machine always calls this method,
+    /// which calls `update` by default.
+    /// When an error is returned, no transition is performed.
+    fn try_update(&mut self, state_data: &mut S) -> Result<StateTransition<S>, StateError> {
+        Ok(self.update(state_data))
+    }
+    /// Fallible version of `fixed_update`. The state machine always calls this method,
+    /// which calls `fixed_update` by default.
+    /// When an error is returned, no transition is performed.
+    fn try_fixed_update(&mut self, state_data: &mut S) -> Result<StateTransition<S>, StateError> {
+        Ok(self.fixed_update(state_data))
+    }
+    /// Executed on every frame, after the update and post update.
+    /// `alpha` is the fraction of a fixed time step that is left over in the accumulator.
+    /// It can be used to interpolate between the previous and current fixed update state.
+    fn render(&mut self, _state_data: &mut S, _alpha: f32) {}
+    /// Called when an event is delivered to the state. The event can be downcasted to
+    /// its original type. Returning `EventResponse::Consumed` stops its delivery.
+    fn handle_event(&mut self, _state_data: &mut S, _event: &dyn Any) -> EventResponse {
+        EventResponse::Ignored
+    }
+    /// A name used to identify the state in debug tools and reports.
+    /// Defaults to the name of the type of the state.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+    /// Whether the state below this one keeps running its updates and fixed updates
+    /// while this state is on top of it. Defaults to false.
+    ///
+    /// Any transition returned by a state below this one, including `Push`, first pops
+    /// this state and the other states above it. A state that should survive the
+    /// transitions of the states below it should not be on the same stack, for example
+    /// by drawing it from a hook instead.
+    fn update_below(&self) -> bool {
+        false
+    }
+    /// Whether the state below this one keeps rendering while this state is on top of it.
+    /// Defaults to false.
+    fn draw_below(&self) -> bool {
+        false
+    }
+}
+
+/// State data that can hold data owned by the states of the stack.
+/// A scope is pushed before a state starts and popped after it stops, so that
+/// the data added to a scope is dropped when its state is popped or switched away.
+/// Scopes are enabled using `StateMachine::enable_scopes`.
+pub trait ScopedData {
+    /// Starts a new scope, owned by the state about to start.
+    fn push_scope(&mut self);
+    /// Ends the most recent scope, dropping its data.
+    fn pop_scope(&mut self);
+}
+
+pub(crate) type ScopeFns<S> = (fn(&mut S), fn(&mut S));
+
+pub(crate) fn scope_fns<S: ScopedData>() -> ScopeFns<S> {
+    (S::push_scope, S::pop_scope)
+}
+
+/// A state machine that holds the stack of states and performs transitions between states.
+/// It can be created using
+/// ```rust,ignore
+/// StateMachine::<()>::default()
+/// ```
+/// ## Generics
+/// - S: State data, the data that is sent to states for them to do their operations.
+pub struct StateMachine<S> {
+    state_stack: Vec<Box<dyn State<S>>>,
+    transition_log: Option<Vec<TransitionKind>>,
+    scopes: Option<ScopeFns<S>>,
+    commands: CommandQueue<S>,
+    exit_result: Option<Box<dyn Any>>,
+    running: Option<usize>,
+}
+
+impl<S> Default for StateMachine<S> {
+    fn default() -> Self {
+        Self {
+            state_stack: Vec::default(),
+            transition_log: None,
+            scopes: None,
+            commands: CommandQueue::default(),
+            exit_result: None,
+            running: None,
+        }
+    }
+}
+
+impl<S> StateMachine<S> {
+    /// Returns if the state machine still has states in its stack.
+    pub fn is_running(&self) -> bool {
+        !self.state_stack.is_empty()
+    }
+
+    /// Returns the number of states in the stack.
+    pub fn depth(&self) -> usize {
+        self.state_stack.len()
+    }
+
+    /// Returns the states of the stack, from the bottom to the top.
+    pub fn states(&self) -> impl Iterator<Item = &dyn State<S>> {
+        self.state_stack.iter().map(|state| state.as_ref())
+    }
+
+    /// Returns the names of the states of the stack, from the bottom to the top.
+    pub fn state_names(&self) -> Vec<&str> {
+        self.states().map(|state| state.name()).collect()
+    }
+
+    /// Returns the state at the top of the stack.
+    pub fn top(&self) -> Option<&dyn State<S>> {
+        self.state_stack.last().map(|state| state.as_ref())
+    }
+
+    /// Updates the state at the top of the stack with the provided data.
+    /// If the states returns a transition, perform it.
+    /// ## Panics
+    /// This will panic if the state returns an error. Use `try_update` to handle it.
+    pub fn update(&mut self, state_data: &mut S) {
+        if let Err(error) = self.try_update(state_data) {
+            panic!("State update failed: {}", error);
+        }
+    }
+
+    /// Updates the state at the top of the stack with the provided data.
+    /// If the states returns a transition, perform it.
+    /// Errors returned by the state are forwarded.
+    ///
+    /// States below the top one also update when all the states above them return true
+    /// from `update_below`. They update from the lowest to the top of the stack.
+    /// When a state below the top returns a transition, the states above it are popped
+    /// in the same batch before the transition is performed, so that it applies to that
+    /// state. The transitions returned by the states above it are discarded.
+    /// When a state fails, no transition is performed.
+    pub fn try_update(&mut self, state_data: &mut S) -> Result<(), StateError> {
+        let lowest = self.lowest_layer(|state| state.update_below());
+        self.try_run_layers(lowest, state_data, |state, state_data| {
+            state.try_update(state_data)
+        })
+    }
+
+    /// Runs the fixed update of the state at the top of the stack with the provided data.
+    /// If the states returns a transition, perform it.
+    /// ## Panics
+    /// This will panic if the state returns an error. Use `try_fixed_update` to handle it.
+    pub fn fixed_update(&mut self, state_data: &mut S) {
+        if let Err(error) = self.try_fixed_update(state_data) {
+            panic!("State fixed update failed: {}", error);
+        }
+    }
+
+    /// Runs the fixed update of the state at the top of the stack with the provided data.
+    /// If the states returns a transition, perform it.
+    /// Errors returned by the state are forwarded.
+    /// States below the top one run like in `try_update`.
+    pub fn try_fixed_update(&mut self, state_data: &mut S) -> Result<(), StateError> {
+        let lowest = self.lowest_layer(|state| state.update_below());
+        self.try_run_layers(lowest, state_data, |state, state_data| {
+            state.try_fixed_update(state_data)
+        })
+    }
+
+    /// Renders the state at the top of the stack with the provided data.
+    /// States below the top one also render, from the lowest to the top of the stack,
+    /// when all the states above them return true from `draw_below`.
+    pub fn render(&mut self, state_data: &mut S, alpha: f32) {
+        let lowest = self.lowest_layer(|state| state.draw_below());
+        for (index, state) in self.state_stack.iter_mut().enumerate().skip(lowest) {
+            self.running = Some(index);
+            state.render(state_data, alpha);
+        }
+        self.running = None;
+    }
+
+    /// Returns the index of the lowest state that runs, given whether each state lets
+    /// the state below it run.
+    fn lowest_layer(&self, runs_below: impl Fn(&dyn State<S>) -> bool) -> usize {
+        let mut lowest = self.state_stack.len().saturating_sub(1);
+        while lowest > 0 && runs_below(self.state_stack[lowest].as_ref()) {
+            lowest -= 1;
+        }
+        lowest
+    }
+
+    /// Runs the states from `lowest` to the top of the stack, then performs the transition
+    /// of the lowest state that returned one. When a state fails, no transition is
+    /// performed and the failing state can be retrieved using `take_failed_state`.
+    fn try_run_layers(
+        &mut self,
+        lowest: usize,
+        state_data: &mut S,
+        mut run: impl FnMut(&mut dyn State<S>, &mut S) -> Result<StateTransition<S>, StateError>,
+    ) -> Result<(), StateError> {
+        let top = self.state_stack.len().saturating_sub(1);
+        let mut transition = None;
+        for (index, state) in self.state_stack.iter_mut().enumerate().skip(lowest) {
+            self.running = Some(index);
+            match run(state.as_mut(), state_data)? {
+                StateTransition::None => (),
+                trans => {
+                    if transition.is_none() {
+                        transition = Some((index, trans));
+                    }
+                }
+            }
+        }
+        self.running = None;
+
+        match transition {
+            Some((index, trans)) if index < top => {
+                // Stop the states above the one that returned the transition, so that the
+                // transition applies to it instead of to the top of the stack.
+                let mut transitions = Vec::with_capacity(top - index + 1);
+                transitions.resize_with(top - index, || StateTransition::Pop);
+                transitions.push(trans);
+                self.transition(StateTransition::Batch(transitions), state_data);
+            }
+            Some((_, trans)) => self.transition(trans, state_data),
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the index in the stack, counting from the bottom, of the state that
+    /// returned an error during the last update or fixed update, and forgets it.
+    /// When a state panics while starting, updating, rendering or handling an event,
+    /// this returns the index of that state instead.
+    pub fn take_failed_state(&mut self) -> Option<usize> {
+        self.running.take()
+    }
+
+    /// Returns a handle used to queue commands changing the state stack.
+    /// The commands are applied by `apply_commands`, which the engine calls on every
+    /// frame after the pre update hooks.
+    pub fn command_sender(&self) -> CommandSender<S> {
+        self.commands.sender().clone()
+    }
+
+    /// Applies the queued commands, in the order they were sent.
+    pub fn apply_commands(&mut self, state_data: &mut S) {
+        for command in self.commands.drain() {
+            match command {
+                StateCommand::Push(state) => self.push(state, state_data),
+                StateCommand::Pop => self.pop(state_data),
+                StateCommand::Switch(state) => self.switch(state, state_data),
+                StateCommand::Quit => self.stop(state_data),
+                StateCommand::Clear => self.pop_from(1, state_data),
+            }
+        }
+    }
+
+    /// Delivers an event to the states, from the top of the stack to the bottom,
+    /// until a state consumes it.
+    pub fn handle_event(&mut self, state_data: &mut S, event: &dyn Any) -> EventResponse {
+        let mut response = EventResponse::Ignored;
+        for (index, state) in self.state_stack.iter_mut().enumerate().rev() {
+            self.running = Some(index);
+            if state.handle_event(state_data, event) == EventResponse::Consumed {
+                response = EventResponse::Consumed;
+                break;
+            }
+        }
+        self.running = None;
+        response
+    }
+
+    /// Enables or disables the recording of the transitions performed.
+    /// Recorded transitions are retrieved using `drain_transitions`.
+    pub fn record_transitions(&mut self, enabled: bool) {
+        self.transition_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns the transitions performed since the last call, from the oldest to the
+    /// most recent. Always empty when recording is disabled.
+    pub fn drain_transitions(&mut self) -> Vec<TransitionKind> {
+        self.transition_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Makes the state machine push a scope of the state data before starting a state,
+    /// and pop it after stopping the state.
+    /// A scope is also pushed for each state already in the stack.
+    pub fn enable_scopes(&mut self, state_data: &mut S)
+    where
+        S: ScopedData,
+    {
+        if self.scopes.is_none() {
+            for _ in self.state_stack.iter() {
+                state_data.push_scope();
+            }
+        }
+        self.scopes = Some(scope_fns());
+    }
+
+    pub(crate) fn set_scopes(&mut self, scopes: Option<ScopeFns<S>>) {
+        self.scopes = scopes;
+    }
+
+    fn start(&mut self, state: Box<dyn State<S>>, state_data: &mut S) {
+        self.exit_result = None;
+        // The state is pushed together with its scope before starting, so that if
+        // `on_start` panics, popping the state later also pops its scope.
+        if let Some((push_scope, _)) = self.scopes {
+            push_scope(state_data);
+        }
+        self.state_stack.push(state);
+        self.running = Some(self.state_stack.len() - 1);
+        if let Some(state) = self.state_stack.last_mut() {
+            state.on_start(state_data);
+        }
+        self.running = None;
+    }
+
+    fn stop_top(&mut self, state_data: &mut S) -> bool {
+        match self.state_stack.pop() {
+            Some(mut state) => {
+                if let Some((_, pop_scope)) = self.scopes {
+                    // Pop the scope even if `on_stop` panics, so that it stays paired
+                    // with the states of the stack.
+                    let stopped =
+                        panic::catch_unwind(AssertUnwindSafe(|| state.on_stop(state_data)));
+                    pop_scope(state_data);
+                    if let Err(payload) = stopped {
+                        panic::resume_unwind(payload);
+                    }
+                } else {
+                    state.on_stop(state_data);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn log(&mut self, kind: TransitionKind) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            transition = ?kind,
+            depth = self.state_stack.len(),
+            "state transition"
+        );
+        if let Some(log) = self.transition_log.as_mut() {
+            log.push(kind);
+        }
+    }
+
+    fn transition(&mut self, request: StateTransition<S>, state_data: &mut S) {
+        match request {
+            StateTransition::None => (),
+            StateTransition::Pop => self.pop(state_data),
+            StateTransition::PopWith(result) => self.pop_with(result, state_data),
+            StateTransition::Push(state) => self.push(state, state_data),
+            StateTransition::Switch(state) => self.switch(state, state_data),
+            StateTransition::Quit => self.stop(state_data),
+            StateTransition::Batch(transitions) => {
+                let mut pending = None;
+                self.batch(transitions, state_data, &mut pending);
+                if let Some(resume) = pending {
+                    self.resume(resume, state_data);
+                }
+            }
+        }
+    }
+
+    /// Performs the transitions of a batch. `pending` is set when the state at the top
+    /// of the stack is still paused, because the state above it was popped.
+    fn batch(
+        &mut self,
+        transitions: Vec<StateTransition<S>>,
+        state_data: &mut S,
+        pending: &mut Option<Resume>,
+    ) {
+        for request in transitions {
+            match request {
+                StateTransition::None => (),
+                StateTransition::Pop | StateTransition::PopWith(_) => {
+                    self.log(TransitionKind::Pop);
+                    self.stop_top(state_data);
+                    let resume = match request {
+                        StateTransition::PopWith(result) => Resume::With(result),
+                        _ => Resume::Plain,
+                    };
+                    *pending = if self.is_running() {
+                        Some(resume)
+                    } else {
+                        self.resume(resume, state_data);
+                        None
+                    };
+                }
+                StateTransition::Push(state) => {
+                    self.log(TransitionKind::Push);
+                    if pending.take().is_none() {
+                        if let Some(state) = self.state_stack.last_mut() {
+                            state.on_pause(state_data);
+                        }
+                    }
+                    self.start(state, state_data);
+                }
+                StateTransition::Switch(state) => {
+                    self.switch(state, state_data);
+                    *pending = None;
+                }
+                StateTransition::Quit => {
+                    self.stop(state_data);
+                    *pending = None;
+                }
+                StateTransition::Batch(transitions) => self.batch(transitions, state_data, pending),
+            }
+        }
+    }
+
+    /// Resumes the state at the top of the stack. When the stack is empty, the result
+    /// is kept as the exit result instead.
+    fn resume(&mut self, resume: Resume, state_data: &mut S) {
+        match (self.state_stack.last_mut(), resume) {
+            (Some(state), Resume::Plain) => state.on_resume(state_data),
+            (Some(state), Resume::With(result)) => state.on_resume_with(state_data, result),
+            (None, Resume::With(result)) => self.exit_result = Some(result),
+            (None, Resume::Plain) => (),
+        }
+    }
+
+    /// Takes the result given by the last state of the stack when it was popped using
+    /// `StateTransition::PopWith`. Starting a state discards it.
+    pub fn take_exit_result(&mut self) -> Option<Box<dyn Any>> {
+        self.exit_result.take()
+    }
+
+    fn switch(&mut self, state: Box<dyn State<S>>, state_data: &mut S) {
+        self.log(TransitionKind::Switch);
+        self.stop_top(state_data);
+        self.start(state, state_data);
+    }
+
+    pub(crate) fn pause_top(&mut self, state_data: &mut S) {
+        if let Some(state) = self.state_stack.last_mut() {
+            state.on_pause(state_data);
+        }
+    }
+
+    pub(crate) fn resume_top(&mut self, result: Option<Box<dyn Any>>, state_data: &mut S) {
+        let resume = match result {
+            Some(result) => Resume::With(result),
+            None => Resume::Plain,
+        };
+        self.resume(resume, state_data);
+    }
+
+    /// Push a state on the stack and start it.
+    /// Pauses any previously active state.
+    pub fn push(&mut self, state: Box<dyn State<S>>, state_data: &mut S) {
+        self.log(TransitionKind::Push);
+        if let Some(state) = self.state_stack.last_mut() {
+            state.on_pause(state_data);
+        }
+
+        self.start(state, state_data);
+    }
+
+    /// Pops the state at the top of the stack and stops it.
+    /// Resumes the state below it, if any.
+    pub fn pop(&mut self, state_data: &mut S) {
+        self.log(TransitionKind::Pop);
+        self.stop_top(state_data);
+        self.resume(Resume::Plain, state_data);
+    }
+
+    /// Pops the state at the top of the stack and stops it.
+    /// Resumes the state below it, if any, giving it the result using `State::on_resume_with`.
+    pub fn pop_with(&mut self, result: Box<dyn Any>, state_data: &mut S) {
+        self.log(TransitionKind::Pop);
+        self.stop_top(state_data);
+        self.resume(Resume::With(result), state_data);
+    }
+
+    /// Pops the state at the given index, along with the states above it.
+    /// The state below it, if any, is resumed once.
+    pub(crate) fn pop_from(&mut self, index: usize, state_data: &mut S) {
+        let mut pops = Vec::new();
+        pops.resize_with(self.state_stack.len().saturating_sub(index), || {
+            StateTransition::Pop
+        });
+        self.transition(StateTransition::Batch(pops), state_data);
+    }
+
+    /// Removes all currently running states from the stack.
+    pub fn stop(&mut self, state_data: &mut S) {
+        self.log(TransitionKind::Quit);
+        while self.stop_top(state_data) {}
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    type StateData = (isize, isize);
+
+    pub struct Test;
+
+    impl State<StateData> for Test {
+        fn on_start(&mut self, data: &mut StateData) {
+            data.0 += data.1;
+        }
+
+        fn on_resume(&mut self, data: &mut StateData) {
+            self.on_start(data);
+        }
+
+        fn update(&mut self, _data: &mut StateData) -> StateTransition<StateData> {
+            StateTransition::Push(Box::new(Test))
+        }
+    }
+
+    #[test]
+    fn sm_test() {
+        let mut sm = StateMachine::<StateData>::default();
+
+        let mut state_data = (0, 10);
+
+        sm.push(Box::new(Test), &mut state_data);
+        assert!(state_data.0 == 10);
+
+        sm.update(&mut state_data);
+        assert!(state_data.0 == 20);
+
+        sm.stop(&mut state_data);
+        assert!(state_data.0 == 20);
+        assert!(!sm.is_running())
+    }
+
+    #[test]
+    fn sm_record_transitions() {
+        let mut sm = StateMachine::<StateData>::default();
+        let mut state_data = (0, 10);
+
+        sm.push(Box::new(Test), &mut state_data);
+        assert!(sm.drain_transitions().is_empty());
+
+        sm.record_transitions(true);
+        sm.update(&mut state_data);
+        sm.stop(&mut state_data);
+        assert_eq!(
+            sm.drain_transitions(),
+            vec![TransitionKind::Push, TransitionKind::Quit]
+        );
+        assert!(sm.drain_transitions().is_empty());
+    }
+
+    pub struct Fixed;
+
+    impl State<StateData> for Fixed {
+        fn fixed_update(&mut self, data: &mut StateData) -> StateTransition<StateData> {
+            data.0 += 1;
+            StateTransition::Pop
+        }
+    }
+
+    struct Layer(&'static str, bool);
+
+    impl State<Vec<&'static str>> for Layer {
+        fn update(&mut self, data: &mut Vec<&'static str>) -> StateTransition<Vec<&'static str>> {
+            data.push(self.0);
+            StateTransition::None
+        }
+        fn render(&mut self, data: &mut Vec<&'static str>, _alpha: f32) {
+            data.push(self.0);
+        }
+        fn update_below(&self) -> bool {
+            self.1
+        }
+        fn draw_below(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn sm_layers() {
+        let mut sm = StateMachine::<Vec<&'static str>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Layer("game", false)), &mut data);
+        sm.push(Box::new(Layer("chat", true)), &mut data);
+        sm.push(Box::new(Layer("hud", true)), &mut data);
+        sm.update(&mut data);
+        assert_eq!(data, vec!["game", "chat", "hud"]);
+
+        data.clear();
+        sm.push(Box::new(Layer("pause", false)), &mut data);
+        sm.update(&mut data);
+        sm.render(&mut data, 0.0);
+        assert_eq!(data, vec!["pause", "game", "chat", "hud", "pause"]);
+    }
+
+    struct Lower;
+
+    impl State<Vec<String>> for Lower {
+        fn on_stop(&mut self, data: &mut Vec<String>) {
+            data.push("stop lower".to_string());
+        }
+        fn update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            StateTransition::Switch(Box::new(Named("next")))
+        }
+        fn fixed_update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            StateTransition::Pop
+        }
+    }
+
+    struct Overlay;
+
+    impl State<Vec<String>> for Overlay {
+        fn on_stop(&mut self, data: &mut Vec<String>) {
+            data.push("stop overlay".to_string());
+        }
+        fn update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            StateTransition::Push(Box::new(Named("discarded")))
+        }
+        fn update_below(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn sm_lower_layer_transition() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Named("root")), &mut data);
+        sm.push(Box::new(Lower), &mut data);
+        sm.push(Box::new(Overlay), &mut data);
+        data.clear();
+        sm.fixed_update(&mut data);
+        assert_eq!(data, vec!["stop overlay", "stop lower", "resume root"]);
+        assert_eq!(sm.state_names(), vec!["root"]);
+
+        sm.push(Box::new(Lower), &mut data);
+        sm.push(Box::new(Overlay), &mut data);
+        data.clear();
+        sm.update(&mut data);
+        assert_eq!(data, vec!["stop overlay", "stop lower", "start next"]);
+        assert_eq!(sm.state_names(), vec!["root", "next"]);
+    }
+
+    struct Failing;
+
+    impl State<Vec<String>> for Failing {
+        fn try_update(
+            &mut self,
+            _data: &mut Vec<String>,
+        ) -> Result<StateTransition<Vec<String>>, StateError> {
+            Err("failed".into())
+        }
+        fn update_below(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn sm_failing_layer() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Named("root")), &mut data);
+        sm.push(Box::new(Lower), &mut data);
+        sm.push(Box::new(Failing), &mut data);
+        data.clear();
+        assert!(sm.try_update(&mut data).is_err());
+        assert_eq!(sm.take_failed_state(), Some(2));
+        assert!(data.is_empty());
+        assert_eq!(sm.depth(), 3);
+        assert_eq!(sm.take_failed_state(), None);
+    }
+
+    #[test]
+    fn sm_commands() {
+        let mut sm = StateMachine::<StateData>::default();
+        let mut state_data = (0, 1);
+        let commands = sm.command_sender();
+
+        sm.push(Box::new(Fixed), &mut state_data);
+        std::thread::spawn(move || {
+            commands.push(Test);
+            commands.push(Test);
+            commands.clear();
+            commands.switch(Test);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(state_data.0, 0);
+
+        sm.record_transitions(true);
+        sm.apply_commands(&mut state_data);
+        assert_eq!(state_data.0, 3);
+        assert_eq!(
+            sm.drain_transitions(),
+            vec![
+                TransitionKind::Push,
+                TransitionKind::Push,
+                TransitionKind::Pop,
+                TransitionKind::Pop,
+                TransitionKind::Switch,
+            ]
+        );
+        assert!(sm.is_running());
+    }
+
+    struct Named(&'static str);
+
+    impl State<Vec<String>> for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn on_start(&mut self, data: &mut Vec<String>) {
+            data.push(format!("start {}", self.0));
+        }
+        fn on_stop(&mut self, data: &mut Vec<String>) {
+            data.push(format!("stop {}", self.0));
+        }
+        fn on_pause(&mut self, data: &mut Vec<String>) {
+            data.push(format!("pause {}", self.0));
+        }
+        fn on_resume(&mut self, data: &mut Vec<String>) {
+            data.push(format!("resume {}", self.0));
+        }
+        fn update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            match self.0 {
+                "options" => StateTransition::Batch(vec![
+                    StateTransition::Pop,
+                    StateTransition::Pop,
+                    StateTransition::Push(Box::new(Named("loading"))),
+                ]),
+                "loading" => StateTransition::Batch(vec![
+                    StateTransition::Pop,
+                    StateTransition::Batch(vec![StateTransition::None]),
+                ]),
+                _ => StateTransition::None,
+            }
+        }
+    }
+
+    #[test]
+    fn sm_batch() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Named("game")), &mut data);
+        sm.push(Box::new(Named("menu")), &mut data);
+        sm.push(Box::new(Named("options")), &mut data);
+        data.clear();
+        sm.update(&mut data);
+        assert_eq!(data, vec!["stop options", "stop menu", "start loading"]);
+
+        data.clear();
+        sm.update(&mut data);
+        assert_eq!(data, vec!["stop loading", "resume game"]);
+    }
+
+    struct Dialog;
+
+    impl State<Vec<String>> for Dialog {
+        fn update(&mut self, _data: &mut Vec<String>) -> StateTransition<Vec<String>> {
+            StateTransition::pop_with(42u32)
+        }
+    }
+
+    struct Parent;
+
+    impl State<Vec<String>> for Parent {
+        fn on_resume(&mut self, data: &mut Vec<String>) {
+            data.push("resume".to_string());
+        }
+        fn on_resume_with(&mut self, data: &mut Vec<String>, result: Box<dyn Any>) {
+            data.push(format!("{:?}", result.downcast::<u32>().ok()));
+        }
+    }
+
+    #[test]
+    fn sm_pop_with() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+
+        sm.push(Box::new(Parent), &mut data);
+        sm.push(Box::new(Dialog), &mut data);
+        sm.update(&mut data);
+        assert_eq!(data, vec!["Some(42)"]);
+
+        sm.push(Box::new(Named("dialog")), &mut data);
+        sm.transition(
+            StateTransition::Batch(vec![
+                StateTransition::Push(Box::new(Named("confirm"))),
+                StateTransition::pop_with(1u32),
+                StateTransition::Pop,
+            ]),
+            &mut data,
+        );
+        sm.pop_with(Box::new("ignored"), &mut data);
+        assert!(!sm.is_running());
+        assert_eq!(
+            data[1..],
+            [
+                "start dialog",
+                "pause dialog",
+                "start confirm",
+                "stop confirm",
+                "stop dialog",
+                "resume",
+            ]
+        );
+    }
+
+    #[test]
+    fn sm_introspection() {
+        let mut sm = StateMachine::<Vec<String>>::default();
+        let mut data = vec![];
+        assert!(sm.top().is_none());
+
+        sm.push(Box::new(Parent), &mut data);
+        sm.push(Box::new(Named("menu")), &mut data);
+        assert_eq!(sm.depth(), 2);
+        assert_eq!(
+            sm.state_names(),
+            vec!["game_engine_core::state_machine::tests::Parent", "menu"]
+        );
+        assert_eq!(sm.top().unwrap().name(), "menu");
+    }
+
+    #[test]
+    fn sm_fixed_update() {
+        let mut sm = StateMachine::<StateData>::default();
+        let mut state_data = (0, 0);
+
+        sm.push(Box::new(Fixed), &mut state_data);
+        sm.update(&mut state_data);
+        assert_eq!(state_data.0, 0);
+
+        sm.fixed_update(&mut state_data);
+        assert_eq!(state_data.0, 1);
+        assert!(!sm.is_running());
+    }
+}
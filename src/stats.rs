@@ -0,0 +1,163 @@
+//! Rolling statistics about the duration of frames.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The default number of frames kept by `FrameStats`.
+pub const DEFAULT_STATS_WINDOW: usize = 120;
+
+/// Keeps the duration of the last frames and computes statistics over them.
+/// All the statistics return None when no frame was recorded yet.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    window: usize,
+    frame_times: VecDeque<Duration>,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATS_WINDOW)
+    }
+}
+
+impl FrameStats {
+    /// Creates a new `FrameStats` keeping the last `window` frames.
+    /// ## Panics
+    /// This will panic if window is 0.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0);
+        Self {
+            window,
+            frame_times: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Returns the number of frames kept.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Sets the number of frames kept. Drops the oldest frames if there are too many.
+    /// ## Panics
+    /// This will panic if window is 0.
+    pub fn set_window(&mut self, window: usize) {
+        assert!(window > 0);
+        self.window = window;
+        while self.frame_times.len() > window {
+            self.frame_times.pop_front();
+        }
+    }
+
+    /// Records the duration of a frame, dropping the oldest one if the window is full.
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// Returns the recorded frame times, from the oldest to the most recent.
+    pub fn frame_times(&self) -> impl Iterator<Item = &Duration> {
+        self.frame_times.iter()
+    }
+
+    /// Forgets all the recorded frames.
+    pub fn clear(&mut self) {
+        self.frame_times.clear();
+    }
+
+    /// Returns the number of frames currently recorded.
+    pub fn len(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    /// Returns true if no frame was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.frame_times.is_empty()
+    }
+
+    /// Returns the mean number of frames per second.
+    pub fn fps(&self) -> Option<f32> {
+        self.average().map(|average| 1.0 / average.as_secs_f32())
+    }
+
+    /// Returns the mean frame time.
+    pub fn average(&self) -> Option<Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        let sum: Duration = self.frame_times.iter().sum();
+        Some(sum / self.frame_times.len() as u32)
+    }
+
+    /// Returns the shortest frame time.
+    pub fn min(&self) -> Option<Duration> {
+        self.frame_times.iter().min().copied()
+    }
+
+    /// Returns the longest frame time.
+    pub fn max(&self) -> Option<Duration> {
+        self.frame_times.iter().max().copied()
+    }
+
+    /// Returns the frame time under which the given fraction of frames are.
+    /// `percentile` goes from 0.0 to 1.0. For example, 0.95 gives the 95th percentile.
+    /// ## Panics
+    /// This will panic if percentile is not between 0.0 and 1.0.
+    pub fn percentile(&self, percentile: f32) -> Option<Duration> {
+        assert!((0.0..=1.0).contains(&percentile));
+        if self.is_empty() {
+            return None;
+        }
+        let mut sorted = self.frame_times.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let rank = (percentile * sorted.len() as f32).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+
+    /// Returns the median frame time.
+    pub fn p50(&self) -> Option<Duration> {
+        self.percentile(0.5)
+    }
+
+    /// Returns the 95th percentile frame time.
+    pub fn p95(&self) -> Option<Duration> {
+        self.percentile(0.95)
+    }
+
+    /// Returns the 99th percentile frame time.
+    pub fn p99(&self) -> Option<Duration> {
+        self.percentile(0.99)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_stats() {
+        let mut stats = FrameStats::new(100);
+        assert_eq!(stats.average(), None);
+        assert_eq!(stats.fps(), None);
+        // The first frames are dropped by the window.
+        for _ in 0..10 {
+            stats.record(Duration::from_secs(1));
+        }
+        for ms in 1..=100 {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.len(), 100);
+        assert_eq!(stats.min(), Some(Duration::from_millis(1)));
+        assert_eq!(stats.max(), Some(Duration::from_millis(100)));
+        assert_eq!(stats.average(), Some(Duration::from_micros(50_500)));
+        assert_eq!(stats.p50(), Some(Duration::from_millis(50)));
+        assert_eq!(stats.p95(), Some(Duration::from_millis(95)));
+        assert_eq!(stats.p99(), Some(Duration::from_millis(99)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(1)));
+
+        stats.set_window(10);
+        assert_eq!(stats.min(), Some(Duration::from_millis(91)));
+    }
+}